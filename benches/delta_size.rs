@@ -0,0 +1,107 @@
+// Size of an export stored in full against delta encoded at a few keyframe
+// intervals, for the window `fetch_data` reads from the 2023 Dutch GP
+// (12:58:56.2 to 13:20:54.3 UTC, 20 cars) on the Zandvoort board. Without
+// network access the session is simulated: cars lap the layout at 3.7
+// samples a second with some position noise. Set `DELTA_DATA` to an exported
+// file to measure a real session instead:
+//
+//     cargo bench --bench delta_size
+//     DELTA_DATA=race.json cargo bench --bench delta_size
+use chrono::{DateTime, Utc};
+use f1_led_circuit_master_simulation::frame_store::FrameStore;
+use f1_led_circuit_master_simulation::frames::{FrameEncoding, VisualizationData};
+use f1_led_circuit_master_simulation::layout::{read_layout, TrackLayout};
+use f1_led_circuit_master_simulation::led_coords::LedCoordinate;
+use f1_led_circuit_master_simulation::{FrameStream, LocationData};
+
+const DRIVERS: u32 = 20;
+const SAMPLE_RATE_HZ: f64 = 3.7; // OpenF1 location samples per car
+const WINDOW_START: &str = "2023-08-27T12:58:56.200Z";
+const WINDOW_END: &str = "2023-08-27T13:20:54.300Z";
+const LAP_SECONDS: f64 = 75.0; // Zandvoort race pace
+const KEYFRAME_INTERVALS: [usize; 4] = [10, 50, 200, 1000];
+
+// Point at `fraction` of a lap along the closed LED polyline
+fn along_track(layout: &TrackLayout, fraction: f64) -> (f64, f64) {
+    let leds = &layout.leds;
+    let segments = || leds.iter().zip(leds.iter().cycle().skip(1));
+    let length = |a: &LedCoordinate, b: &LedCoordinate| (b.x_led - a.x_led).hypot(b.y_led - a.y_led);
+    let total: f64 = segments().map(|(a, b)| length(a, b)).sum();
+
+    let mut left = fraction.rem_euclid(1.0) * total;
+    for (a, b) in segments() {
+        let step = length(a, b);
+        if left <= step {
+            let t = left / step.max(f64::EPSILON);
+            return (a.x_led + (b.x_led - a.x_led) * t, a.y_led + (b.y_led - a.y_led) * t);
+        }
+        left -= step;
+    }
+    (leds[0].x_led, leds[0].y_led)
+}
+
+// Every car's samples over the window, in time order like `fetch_data` sorts them
+fn session_samples(layout: &TrackLayout) -> Vec<LocationData> {
+    let start = DateTime::parse_from_rfc3339(WINDOW_START).unwrap().with_timezone(&Utc);
+    let end = DateTime::parse_from_rfc3339(WINDOW_END).unwrap().with_timezone(&Utc);
+    let seconds = (end - start).num_milliseconds() as f64 / 1000.0;
+
+    let mut samples = Vec::new();
+    for driver in 0..DRIVERS {
+        // A grid spread over a few seconds and slightly different paces
+        let lap = LAP_SECONDS + driver as f64 * 0.2;
+        let gap = driver as f64 * 0.004;
+        for sample in 0..(seconds * SAMPLE_RATE_HZ) as usize {
+            let time = sample as f64 / SAMPLE_RATE_HZ + driver as f64 * 0.013;
+            let (x, y) = along_track(layout, time / lap - gap);
+            samples.push(LocationData {
+                x: (x + (sample * 7 % 11) as f64 - 5.0).round(),
+                y: (y + (sample * 5 % 9) as f64 - 4.0).round(),
+                date: start + chrono::Duration::milliseconds((time * 1000.0) as i64),
+                driver_number: driver + 1,
+            });
+        }
+    }
+    samples.sort_by_key(|sample| sample.date);
+    samples
+}
+
+fn size(data: &mut VisualizationData, encoding: FrameEncoding) -> usize {
+    data.encoding = encoding;
+    serde_json::to_vec(data).unwrap().len()
+}
+
+fn report(name: &str, data: &mut VisualizationData) {
+    let full = size(data, FrameEncoding::Full);
+    println!("{}: {} frames, {} bytes full", name, data.frames.len(), full);
+    for keyframe_interval in KEYFRAME_INTERVALS {
+        let delta = size(data, FrameEncoding::Delta { keyframe_interval });
+        println!(
+            "    keyframe every {:>4}: {:>9} bytes delta ({:.1}% smaller)",
+            keyframe_interval,
+            delta,
+            100.0 * (1.0 - delta as f64 / full as f64)
+        );
+    }
+}
+
+fn main() {
+    let mut data = match std::env::var_os("DELTA_DATA") {
+        Some(path) => VisualizationData::load(path.as_ref()).unwrap(),
+        None => {
+            let layout = read_layout().unwrap();
+            let samples = session_samples(&layout);
+            VisualizationData {
+                update_rate_ms: 100,
+                frames: FrameStore::from_frames(FrameStream::new(samples.into_iter(), DRIVERS as usize, &layout, &[]))
+                    .unwrap(),
+                metadata: None,
+                encoding: FrameEncoding::Full,
+            }
+        }
+    };
+
+    report("With raw positions", &mut data);
+    data.frames.clear_positions();
+    report("Without positions (--no-positions)", &mut data);
+}
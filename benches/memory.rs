@@ -0,0 +1,135 @@
+// Peak heap use of loading, mapping and playing back a full race and a race
+// weekend from synthetic samples, counted by a wrapping global allocator.
+// Fails when the compact path goes over its budget:
+//
+//     cargo bench --bench memory
+use std::io;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use chrono::{DateTime, Utc};
+use f1_led_circuit_master_simulation::driver_info::{get_driver_info, Rgb};
+use f1_led_circuit_master_simulation::frame_store::FrameStore;
+use f1_led_circuit_master_simulation::hardware::LedOutput;
+use f1_led_circuit_master_simulation::layout::{read_layout, TrackLayout};
+use f1_led_circuit_master_simulation::playback::{OutputStyle, Playback, PlaybackEvent};
+use f1_led_circuit_master_simulation::samples::LocationSamples;
+use f1_led_circuit_master_simulation::sinks::{OutputSinks, SinkOptions};
+use f1_led_circuit_master_simulation::{FrameStream, LocationData, UpdateFrame};
+
+mod counting;
+use counting::{measure, MIB};
+
+const DRIVERS: u32 = 20;
+const SAMPLE_RATE_HZ: f64 = 3.7; // OpenF1 location samples per car
+const RACE_HOURS: f64 = 2.0;
+// Practice, qualifying and race
+const WEEKEND_SESSION_HOURS: [f64; 5] = [1.0, 1.0, 1.0, 1.0, 2.0];
+const RACE_BUDGET: usize = 48 * MIB;
+const WEEKEND_BUDGET: usize = 96 * MIB;
+
+// One driver's samples for a session, as an OpenF1 response would hold
+// them: cars lapping the layout at slightly different paces
+fn driver_samples(layout: &TrackLayout, start: DateTime<Utc>, hours: f64, driver: u32) -> Vec<LocationData> {
+    let count = (hours * 3600.0 * SAMPLE_RATE_HZ) as usize;
+    let interval_ms = 1000.0 / SAMPLE_RATE_HZ;
+    let leds = &layout.leds;
+    (0..count)
+        .map(|sample| {
+            let position = sample * (3 + driver as usize % 3) / 2 + driver as usize * 5;
+            let coord = &leds[position % leds.len()];
+            LocationData {
+                x: (coord.x_led + (sample % 7) as f64).round(),
+                y: (coord.y_led - (sample % 5) as f64).round(),
+                date: start + chrono::Duration::milliseconds((sample as f64 * interval_ms) as i64 + driver as i64 * 13),
+                driver_number: driver + 1,
+            }
+        })
+        .collect()
+}
+
+// Fetch-like loading into the columnar store, then mapping straight into frames
+fn load_session(layout: &TrackLayout, hours: f64) -> FrameStore {
+    let start = DateTime::parse_from_rfc3339("2023-08-27T13:00:00Z").unwrap().with_timezone(&Utc);
+    let mut samples = LocationSamples::new();
+    for driver in 0..DRIVERS {
+        samples.extend(driver_samples(layout, start, hours, driver)).unwrap();
+    }
+    samples.sort_by_date();
+    samples.shrink_to_fit();
+
+    let grid_size = samples.driver_numbers().len();
+    FrameStore::from_frames(FrameStream::new(samples.iter(), grid_size, layout, &[])).unwrap()
+}
+
+// The old representation, for comparison: every sample and frame as a struct
+fn load_session_uncompacted(layout: &TrackLayout, hours: f64) -> Vec<UpdateFrame> {
+    let start = DateTime::parse_from_rfc3339("2023-08-27T13:00:00Z").unwrap().with_timezone(&Utc);
+    let mut samples: Vec<LocationData> = Vec::new();
+    for driver in 0..DRIVERS {
+        samples.extend(driver_samples(layout, start, hours, driver));
+    }
+    samples.sort_by_key(|sample| sample.date);
+
+    FrameStream::new(samples.into_iter(), DRIVERS as usize, layout, &[]).collect()
+}
+
+struct Discard;
+
+impl LedOutput for Discard {
+    fn send(&mut self, _colors: &[Rgb]) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+// Plays the whole session through the playback engine, much faster than real time
+fn play(layout: &TrackLayout, frames: FrameStore) -> usize {
+    let playback = Playback::new(100, Arc::new(frames), layout.clone());
+    let mut outputs = OutputSinks::new();
+    outputs.add("discard", Box::new(Discard), SinkOptions::default());
+    playback.set_outputs(outputs);
+    playback.set_style(OutputStyle {
+        driver_info: get_driver_info(),
+        ..OutputStyle::default()
+    });
+    playback.set_speed(20_000.0).unwrap();
+
+    let events = playback.subscribe();
+    playback.start();
+    let mut shown = 0;
+    while let Ok(event) = events.recv_timeout(Duration::from_secs(30)) {
+        match event {
+            PlaybackEvent::Frame { .. } => shown += 1,
+            PlaybackEvent::Finished => break,
+        }
+    }
+    shown
+}
+
+fn check(name: &str, peak: usize, budget: usize) {
+    println!("{}: peak {:.1} MiB (budget {} MiB)", name, peak as f64 / MIB as f64, budget / MIB);
+    assert!(peak <= budget, "{} went over its memory budget", name);
+}
+
+fn main() {
+    let layout = read_layout().unwrap();
+
+    let started = Instant::now();
+    let (shown, peak) = measure(|| play(&layout, load_session(&layout, RACE_HOURS)));
+    println!("Race: {} frame changes shown in {:.1?}", shown, started.elapsed());
+    check("Race", peak, RACE_BUDGET);
+
+    let (_, peak) = measure(|| load_session_uncompacted(&layout, RACE_HOURS));
+    println!("Race as Vec<LocationData> and Vec<UpdateFrame>: peak {:.1} MiB", peak as f64 / MIB as f64);
+
+    // Every session of the weekend loaded at once, then the race played
+    let (_, peak) = measure(|| {
+        let mut sessions: Vec<FrameStore> =
+            WEEKEND_SESSION_HOURS.iter().map(|&hours| load_session(&layout, hours)).collect();
+        let race = sessions.pop().unwrap();
+        let held: usize = sessions.iter().map(FrameStore::heap_bytes).sum::<usize>() + race.heap_bytes();
+        println!("Weekend: {:.1} MiB of frames held", held as f64 / MIB as f64);
+        play(&layout, race)
+    });
+    check("Weekend", peak, WEEKEND_BUDGET);
+}
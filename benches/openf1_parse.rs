@@ -0,0 +1,132 @@
+// Time and peak heap use of reading an OpenF1 `location` response the way
+// `fetch_data` used to, buffering the body and deserializing a
+// `Vec<LocationData>` with chrono's RFC 3339 parser, against parsing it as it
+// downloads with `LocationParser`. Fails if the streaming path needs more
+// memory or reads different samples:
+//
+//     cargo bench --bench openf1_parse
+use std::time::{Duration, Instant};
+
+use chrono::{DateTime, SecondsFormat, Utc};
+use f1_led_circuit_master_simulation::location_parser::LocationParser;
+use f1_led_circuit_master_simulation::samples::LocationSamples;
+use serde::de::{self, Deserializer};
+use serde::Deserialize;
+
+mod counting;
+use counting::{measure, MIB};
+
+const SAMPLE_RATE_HZ: f64 = 3.7; // OpenF1 location samples per car
+const RACE_HOURS: f64 = 2.0;
+const CHUNK_BYTES: usize = 16 * 1024; // About what reqwest hands over at a time
+const RUNS: usize = 5;
+
+// A whole race of one driver, formatted like the API's responses
+fn response_body() -> Vec<u8> {
+    let start = DateTime::parse_from_rfc3339("2023-08-27T13:00:00Z").unwrap().with_timezone(&Utc);
+    let count = (RACE_HOURS * 3600.0 * SAMPLE_RATE_HZ) as usize;
+    let records: Vec<String> = (0..count)
+        .map(|sample| {
+            let date = start + chrono::Duration::microseconds((sample as f64 * 1e6 / SAMPLE_RATE_HZ) as i64);
+            format!(
+                r#"{{"x":{},"y":{},"z":{},"driver_number":1,"date":"{}","session_key":9149,"meeting_key":1217}}"#,
+                (sample % 9000) as i64 - 4000,
+                (sample % 7000) as i64 - 3000,
+                sample % 30,
+                date.to_rfc3339_opts(SecondsFormat::Micros, false),
+            )
+        })
+        .collect();
+    format!("[{}]", records.join(",")).into_bytes()
+}
+
+#[derive(Deserialize)]
+struct BufferedLocation {
+    x: f64,
+    y: f64,
+    #[serde(deserialize_with = "rfc3339")]
+    date: DateTime<Utc>,
+    driver_number: u32,
+}
+
+fn rfc3339<'de, D>(deserializer: D) -> Result<DateTime<Utc>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    let s: String = Deserialize::deserialize(deserializer)?;
+    DateTime::parse_from_rfc3339(&s)
+        .map_err(de::Error::custom)
+        .map(|dt| dt.with_timezone(&Utc))
+}
+
+// `resp.json()`: the whole body, then every record, then the store
+fn read_buffered(body: &[u8]) -> LocationSamples {
+    let body = body.to_vec();
+    let data: Vec<BufferedLocation> = serde_json::from_slice(&body).unwrap();
+    let mut samples = LocationSamples::new();
+    samples
+        .extend(data.into_iter().filter(|d| d.x != 0.0 && d.y != 0.0).map(|d| {
+            f1_led_circuit_master_simulation::LocationData {
+                x: d.x,
+                y: d.y,
+                date: d.date,
+                driver_number: d.driver_number,
+            }
+        }))
+        .unwrap();
+    samples
+}
+
+// `resp.chunk()`: one chunk at a time, straight into the store
+fn read_streaming(body: &[u8]) -> LocationSamples {
+    let mut samples = LocationSamples::new();
+    let mut parser = LocationParser::new();
+    for chunk in body.chunks(CHUNK_BYTES) {
+        let chunk = chunk.to_vec();
+        parser
+            .push(&chunk, |d| {
+                if d.x != 0.0 && d.y != 0.0 {
+                    samples.push(&d).unwrap();
+                }
+            })
+            .unwrap();
+    }
+    parser.finish().unwrap();
+    samples
+}
+
+// Fastest of a few runs and the peak heap use of the first
+fn run(read: impl Fn(&[u8]) -> LocationSamples, body: &[u8]) -> (LocationSamples, Duration, usize) {
+    let (samples, peak) = measure(|| read(body));
+    let fastest = (0..RUNS)
+        .map(|_| {
+            let started = Instant::now();
+            read(body);
+            started.elapsed()
+        })
+        .min()
+        .unwrap();
+    (samples, fastest, peak)
+}
+
+fn main() {
+    let body = response_body();
+    println!("Response: {:.1} MiB", body.len() as f64 / MIB as f64);
+
+    let (buffered, buffered_time, buffered_peak) = run(read_buffered, &body);
+    let (streamed, streamed_time, streamed_peak) = run(read_streaming, &body);
+    println!(
+        "Buffered:  {:.1?}, peak {:.1} MiB",
+        buffered_time,
+        buffered_peak as f64 / MIB as f64
+    );
+    println!(
+        "Streaming: {:.1?}, peak {:.1} MiB ({:.1}x faster)",
+        streamed_time,
+        streamed_peak as f64 / MIB as f64,
+        buffered_time.as_secs_f64() / streamed_time.as_secs_f64()
+    );
+
+    assert!(buffered.iter().eq(streamed.iter()), "streaming read different samples");
+    assert!(streamed_peak < buffered_peak, "streaming used more memory than buffering");
+}
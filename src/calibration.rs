@@ -0,0 +1,473 @@
+use serde::{Deserialize, Serialize};
+use std::error::Error as StdError;
+use std::fs;
+use std::path::Path;
+
+use crate::layout::TrackLayout;
+use crate::layout_gen::nearest_on_polyline;
+use crate::led_coords::LedCoordinate;
+
+// Known track position of one LED, used to anchor the PCB layout
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ReferencePoint {
+    pub led_number: usize,
+    pub x: f64,
+    pub y: f64,
+}
+
+pub fn read_reference_points(path: &Path) -> Result<Vec<ReferencePoint>, Box<dyn StdError>> {
+    let file = fs::File::open(path)?;
+    Ok(serde_json::from_reader(file)?)
+}
+
+// 2D affine transform: (x, y) -> (a*x + b*y + tx, c*x + d*y + ty)
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Transform2D {
+    pub a: f64,
+    pub b: f64,
+    pub c: f64,
+    pub d: f64,
+    pub tx: f64,
+    pub ty: f64,
+}
+
+impl Transform2D {
+    pub fn apply(&self, (x, y): (f64, f64)) -> (f64, f64) {
+        (self.a * x + self.b * y + self.tx, self.c * x + self.d * y + self.ty)
+    }
+
+    pub fn scale(&self) -> f64 {
+        (self.a * self.d - self.b * self.c).abs().sqrt()
+    }
+
+    pub fn rotation_degrees(&self) -> f64 {
+        self.c.atan2(self.a).to_degrees()
+    }
+
+    pub fn is_mirrored(&self) -> bool {
+        self.a * self.d - self.b * self.c < 0.0
+    }
+
+    pub fn apply_to_layout(&self, layout: &TrackLayout) -> TrackLayout {
+        let mut transformed = layout.clone();
+        for coord in &mut transformed.leds {
+            let (x, y) = self.apply((coord.x_led, coord.y_led));
+            coord.x_led = x;
+            coord.y_led = y;
+        }
+        for point in &mut transformed.outline {
+            *point = self.apply(*point);
+        }
+        transformed
+    }
+}
+
+fn centroid(points: &[(f64, f64)]) -> (f64, f64) {
+    let n = points.len() as f64;
+    let (sx, sy) = points
+        .iter()
+        .fold((0.0, 0.0), |(sx, sy), (x, y)| (sx + x, sy + y));
+    (sx / n, sy / n)
+}
+
+// Least squares similarity transform (scale, rotation, translation) mapping
+// `src` onto `dst`; with `allow_mirror` a reflected fit is used when better.
+pub fn fit_similarity(src: &[(f64, f64)], dst: &[(f64, f64)], allow_mirror: bool) -> Option<Transform2D> {
+    if src.len() < 2 || src.len() != dst.len() {
+        return None;
+    }
+
+    let fit = |mirror: bool| -> Option<Transform2D> {
+        let flip = if mirror { -1.0 } else { 1.0 };
+        let src: Vec<(f64, f64)> = src.iter().map(|&(x, y)| (flip * x, y)).collect();
+        let (scx, scy) = centroid(&src);
+        let (dcx, dcy) = centroid(dst);
+
+        let (mut dot, mut cross, mut norm) = (0.0, 0.0, 0.0);
+        for (&(sx, sy), &(dx, dy)) in src.iter().zip(dst) {
+            let (px, py) = (sx - scx, sy - scy);
+            let (qx, qy) = (dx - dcx, dy - dcy);
+            dot += px * qx + py * qy;
+            cross += px * qy - py * qx;
+            norm += px * px + py * py;
+        }
+        if norm == 0.0 {
+            return None;
+        }
+
+        let a = dot / norm; // scale * cos
+        let c = cross / norm; // scale * sin
+        let transform = Transform2D {
+            a: a * flip,
+            b: -c,
+            c: c * flip,
+            d: a,
+            tx: dcx - (a * scx - c * scy),
+            ty: dcy - (c * scx + a * scy),
+        };
+        Some(transform)
+    };
+
+    let plain = fit(false)?;
+    if !allow_mirror {
+        return Some(plain);
+    }
+    match fit(true) {
+        Some(mirrored) if rms_error(&mirrored, src, dst) < rms_error(&plain, src, dst) => Some(mirrored),
+        _ => Some(plain),
+    }
+}
+
+// Least squares general affine transform, needs three non-collinear points
+pub fn fit_affine(src: &[(f64, f64)], dst: &[(f64, f64)]) -> Option<Transform2D> {
+    if src.len() < 3 || src.len() != dst.len() {
+        return None;
+    }
+
+    // Normal equations M * [a, b, t] = v for each output axis
+    let mut m = [[0.0; 3]; 3];
+    let mut vx = [0.0; 3];
+    let mut vy = [0.0; 3];
+    for (&(x, y), &(u, v)) in src.iter().zip(dst) {
+        let row = [x, y, 1.0];
+        for i in 0..3 {
+            for j in 0..3 {
+                m[i][j] += row[i] * row[j];
+            }
+            vx[i] += row[i] * u;
+            vy[i] += row[i] * v;
+        }
+    }
+
+    let [a, b, tx] = solve3(m, vx)?;
+    let [c, d, ty] = solve3(m, vy)?;
+    Some(Transform2D { a, b, c, d, tx, ty })
+}
+
+// Cramer's rule for a 3x3 system
+fn solve3(m: [[f64; 3]; 3], v: [f64; 3]) -> Option<[f64; 3]> {
+    let det = |m: &[[f64; 3]; 3]| {
+        m[0][0] * (m[1][1] * m[2][2] - m[1][2] * m[2][1])
+            - m[0][1] * (m[1][0] * m[2][2] - m[1][2] * m[2][0])
+            + m[0][2] * (m[1][0] * m[2][1] - m[1][1] * m[2][0])
+    };
+    // Singular up to rounding: the determinant is tiny next to the largest it
+    // could be for rows of this size (Hadamard's bound), e.g. collinear points
+    let bound: f64 = m.iter().map(|row| row.iter().map(|v| v * v).sum::<f64>().sqrt()).product();
+    let base = det(&m);
+    if base.abs() <= 1e-9 * bound {
+        return None;
+    }
+
+    let mut solution = [0.0; 3];
+    for (col, value) in solution.iter_mut().enumerate() {
+        let mut replaced = m;
+        for row in 0..3 {
+            replaced[row][col] = v[row];
+        }
+        *value = det(&replaced) / base;
+    }
+    Some(solution)
+}
+
+pub fn rms_error(transform: &Transform2D, src: &[(f64, f64)], dst: &[(f64, f64)]) -> f64 {
+    let sum: f64 = src
+        .iter()
+        .zip(dst)
+        .map(|(&s, &(dx, dy))| {
+            let (x, y) = transform.apply(s);
+            (x - dx).powi(2) + (y - dy).powi(2)
+        })
+        .sum();
+    (sum / src.len().max(1) as f64).sqrt()
+}
+
+// Nearest point on the reference lap, between its samples too
+fn nearest_point(point: (f64, f64), reference: &[(f64, f64)]) -> (f64, f64) {
+    nearest_on_polyline(reference, point).unwrap_or(point)
+}
+
+// Iterative closest point fit of `src` onto a reference lap polyline. Starts
+// from a centroid/spread alignment at four rotations (and their mirrors) and
+// keeps the best converged result.
+pub fn fit_icp(src: &[(f64, f64)], reference: &[(f64, f64)], iterations: usize) -> Option<Transform2D> {
+    if src.len() < 2 || reference.is_empty() {
+        return None;
+    }
+
+    let spread = |points: &[(f64, f64)]| {
+        let (cx, cy) = centroid(points);
+        let sum: f64 = points.iter().map(|(x, y)| (x - cx).powi(2) + (y - cy).powi(2)).sum();
+        ((cx, cy), (sum / points.len() as f64).sqrt())
+    };
+    let ((scx, scy), src_spread) = spread(src);
+    let ((rcx, rcy), ref_spread) = spread(reference);
+    if src_spread == 0.0 {
+        return None;
+    }
+    let scale = ref_spread / src_spread;
+
+    let mut best: Option<(Transform2D, f64)> = None;
+    for mirror in [false, true] {
+        for quarter in 0..4 {
+            let angle = quarter as f64 * std::f64::consts::FRAC_PI_2;
+            let flip = if mirror { -1.0 } else { 1.0 };
+            let (cos, sin) = (scale * angle.cos(), scale * angle.sin());
+            let mut transform = Transform2D {
+                a: cos * flip,
+                b: -sin,
+                c: sin * flip,
+                d: cos,
+                tx: 0.0,
+                ty: 0.0,
+            };
+            let (ox, oy) = transform.apply((scx, scy));
+            transform.tx = rcx - ox;
+            transform.ty = rcy - oy;
+
+            for _ in 0..iterations {
+                let matched: Vec<(f64, f64)> = src
+                    .iter()
+                    .map(|&p| nearest_point(transform.apply(p), reference))
+                    .collect();
+                match fit_similarity(src, &matched, mirror) {
+                    Some(next) if next != transform => transform = next,
+                    _ => break,
+                }
+            }
+
+            let matched: Vec<(f64, f64)> = src
+                .iter()
+                .map(|&p| nearest_point(transform.apply(p), reference))
+                .collect();
+            let error = rms_error(&transform, src, &matched);
+            let better = match best {
+                Some((_, best_error)) => error < best_error,
+                None => true,
+            };
+            if better {
+                best = Some((transform, error));
+            }
+        }
+    }
+    best.map(|(transform, _)| transform)
+}
+
+pub struct CalibrationReport {
+    pub transform: Transform2D,
+    pub residuals: Vec<(usize, f64)>, // (led_number, distance in track units)
+}
+
+impl CalibrationReport {
+    pub fn rms(&self) -> f64 {
+        let n = self.residuals.len().max(1) as f64;
+        (self.residuals.iter().map(|(_, r)| r * r).sum::<f64>() / n).sqrt()
+    }
+
+    pub fn summary(&self) -> String {
+        let mut text = format!(
+            "scale {:.4}, rotation {:.2} deg, translation ({:.1}, {:.1}){}\n",
+            self.transform.scale(),
+            self.transform.rotation_degrees(),
+            self.transform.tx,
+            self.transform.ty,
+            if self.transform.is_mirrored() { ", mirrored" } else { "" },
+        );
+        for (led_number, residual) in &self.residuals {
+            text.push_str(&format!("U{}: {:.1}\n", led_number, residual));
+        }
+        text.push_str(&format!("RMS residual: {:.1}\n", self.rms()));
+        text
+    }
+}
+
+fn led_points(leds: &[&LedCoordinate]) -> Vec<(f64, f64)> {
+    leds.iter().map(|coord| (coord.x_led, coord.y_led)).collect()
+}
+
+// Fits the PCB layout to known track positions of some of its LEDs
+pub fn calibrate_with_points(
+    pcb: &TrackLayout,
+    points: &[ReferencePoint],
+    affine: bool,
+) -> Result<CalibrationReport, Box<dyn StdError>> {
+    let mut leds = Vec::new();
+    let mut dst = Vec::new();
+    for point in points {
+        let coord = pcb
+            .coordinate(point.led_number)
+            .ok_or_else(|| format!("Reference point for unknown LED {}", point.led_number))?;
+        leds.push(coord);
+        dst.push((point.x, point.y));
+    }
+    let src = led_points(&leds);
+
+    let transform = if affine {
+        fit_affine(&src, &dst)
+    } else {
+        fit_similarity(&src, &dst, true)
+    }
+    .ok_or("Not enough independent reference points to fit a transform")?;
+
+    let residuals = leds
+        .iter()
+        .zip(&dst)
+        .map(|(coord, &(dx, dy))| {
+            let (x, y) = transform.apply((coord.x_led, coord.y_led));
+            (coord.led_number, ((x - dx).powi(2) + (y - dy).powi(2)).sqrt())
+        })
+        .collect();
+    Ok(CalibrationReport { transform, residuals })
+}
+
+// Fits the PCB layout to a reference lap; residuals are distances to the lap
+pub fn calibrate_with_lap(pcb: &TrackLayout, lap: &[(f64, f64)]) -> Result<CalibrationReport, Box<dyn StdError>> {
+    let leds: Vec<&LedCoordinate> = pcb.leds.iter().collect();
+    let src = led_points(&leds);
+    let transform = fit_icp(&src, lap, 50).ok_or("Cannot align layout to an empty reference lap")?;
+
+    let residuals = leds
+        .iter()
+        .map(|coord| {
+            let point = transform.apply((coord.x_led, coord.y_led));
+            let (nx, ny) = nearest_point(point, lap);
+            (coord.led_number, ((point.0 - nx).powi(2) + (point.1 - ny).powi(2)).sqrt())
+        })
+        .collect();
+    Ok(CalibrationReport { transform, residuals })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn lap_residuals_are_distances_to_the_line() {
+        // A square lap sampled only at its corners, LEDs halfway along two sides
+        let lap = [(0.0, 0.0), (1000.0, 0.0), (1000.0, 1000.0), (0.0, 1000.0)];
+        let leds = [(500.0, 3.0), (997.0, 500.0), (500.0, 1000.0), (0.0, 500.0), (0.0, 0.0), (1000.0, 1000.0)]
+            .iter()
+            .enumerate()
+            .map(|(i, &(x_led, y_led))| LedCoordinate { x_led, y_led, led_number: i + 1 })
+            .collect();
+        let pcb = TrackLayout::from_coordinates("pcb", "Test", leds);
+
+        let report = calibrate_with_lap(&pcb, &lap).unwrap();
+        // Nearest vertices would be 500 away
+        assert!(report.rms() < 5.0, "RMS residual {}", report.rms());
+        assert!(report.residuals.iter().all(|&(_, residual)| residual < 5.0));
+    }
+
+    // Rotation by `degrees`, `scale` and a shift, mirrored in x first if asked
+    fn transform(degrees: f64, scale: f64, mirror: bool, (tx, ty): (f64, f64)) -> Transform2D {
+        let (sin, cos) = degrees.to_radians().sin_cos();
+        let flip = if mirror { -1.0 } else { 1.0 };
+        Transform2D { a: scale * cos * flip, b: -scale * sin, c: scale * sin * flip, d: scale * cos, tx, ty }
+    }
+
+    fn assert_close(found: &Transform2D, expected: &Transform2D) {
+        let (f, e) = (found, expected);
+        let pairs = [(f.a, e.a), (f.b, e.b), (f.c, e.c), (f.d, e.d), (f.tx, e.tx), (f.ty, e.ty)];
+        assert!(pairs.iter().all(|(f, e)| (f - e).abs() < 1e-6), "{:?} is not {:?}", found, expected);
+    }
+
+    const PCB: [(f64, f64); 5] = [(0.0, 0.0), (120.0, 10.0), (200.0, 90.0), (60.0, 150.0), (-30.0, 70.0)];
+
+    #[test]
+    fn known_transforms_are_recovered() {
+        for mirror in [false, true] {
+            let expected = transform(37.0, 4.5, mirror, (1200.0, -350.0));
+            let dst: Vec<(f64, f64)> = PCB.iter().map(|&p| expected.apply(p)).collect();
+
+            let similarity = fit_similarity(&PCB, &dst, true).unwrap();
+            assert_close(&similarity, &expected);
+            assert_eq!(similarity.is_mirrored(), mirror);
+            assert_close(&fit_affine(&PCB, &dst).unwrap(), &expected);
+        }
+
+        // Without mirroring allowed, a mirrored layout is not matched exactly
+        let mirrored = transform(37.0, 4.5, true, (0.0, 0.0));
+        let dst: Vec<(f64, f64)> = PCB.iter().map(|&p| mirrored.apply(p)).collect();
+        let plain = fit_similarity(&PCB, &dst, false).unwrap();
+        assert!(!plain.is_mirrored() && rms_error(&plain, &PCB, &dst) > 1.0);
+
+        // A shear only the affine fit can follow
+        let sheared = Transform2D { a: 2.0, b: 0.7, c: -0.3, d: 1.5, tx: 40.0, ty: 8.0 };
+        let dst: Vec<(f64, f64)> = PCB.iter().map(|&p| sheared.apply(p)).collect();
+        assert_close(&fit_affine(&PCB, &dst).unwrap(), &sheared);
+    }
+
+    fn pcb() -> TrackLayout {
+        let leds = PCB
+            .iter()
+            .enumerate()
+            .map(|(i, &(x_led, y_led))| LedCoordinate { x_led, y_led, led_number: i + 1 })
+            .collect();
+        TrackLayout::from_coordinates("pcb", "Test", leds)
+    }
+
+    fn reference_points(leds: &[usize], transform: &Transform2D) -> Vec<ReferencePoint> {
+        leds.iter()
+            .map(|&led_number| {
+                let (x, y) = transform.apply(PCB[led_number - 1]);
+                ReferencePoint { led_number, x, y }
+            })
+            .collect()
+    }
+
+    #[test]
+    fn calibrating_with_points_round_trips() {
+        let expected = transform(-120.0, 0.25, true, (15.0, 30.0));
+        for affine in [false, true] {
+            let report = calibrate_with_points(&pcb(), &reference_points(&[1, 2, 3, 4], &expected), affine).unwrap();
+            assert_close(&report.transform, &expected);
+            assert!(report.rms() < 1e-6);
+            assert_eq!(report.residuals.iter().map(|&(led, _)| led).collect::<Vec<_>>(), [1, 2, 3, 4]);
+        }
+    }
+
+    #[test]
+    fn degenerate_reference_points_are_errors() {
+        let expected = transform(10.0, 2.0, false, (5.0, 5.0));
+        let pcb = pcb();
+        // One point, or two points for an affine fit
+        assert!(calibrate_with_points(&pcb, &reference_points(&[1], &expected), false).is_err());
+        assert!(calibrate_with_points(&pcb, &reference_points(&[1, 2], &expected), true).is_err());
+        // The same LED twice
+        assert!(calibrate_with_points(&pcb, &reference_points(&[3, 3, 3], &expected), false).is_err());
+        assert!(calibrate_with_points(&pcb, &reference_points(&[3, 3, 3], &expected), true).is_err());
+        // An unknown LED
+        let unknown = [ReferencePoint { led_number: 9, x: 0.0, y: 0.0 }, ReferencePoint { led_number: 1, x: 1.0, y: 1.0 }];
+        assert!(calibrate_with_points(&pcb, &unknown, false).is_err());
+
+        // Collinear LEDs cannot fix a shear, even with awkward coordinates
+        let line: Vec<(f64, f64)> = [12.7, 155.3, 402.9, 811.1].iter().map(|&x| (x, 0.3 * x + 7.1)).collect();
+        let dst: Vec<(f64, f64)> = line.iter().map(|&p| expected.apply(p)).collect();
+        assert!(fit_affine(&line, &dst).is_none());
+        // A similarity fit only needs two distinct points
+        assert_close(&fit_similarity(&line, &dst, false).unwrap(), &expected);
+    }
+
+    #[test]
+    fn icp_converges_from_an_offset_start() {
+        // An irregular closed lap, densely sampled, and LEDs on a rotated,
+        // scaled and shifted copy of it
+        let corners = [(0.0, 0.0), (900.0, 0.0), (1200.0, 500.0), (700.0, 450.0), (400.0, 900.0), (-100.0, 600.0)];
+        let mut lap = Vec::new();
+        for (i, &(x1, y1)) in corners.iter().enumerate() {
+            let (x2, y2) = corners[(i + 1) % corners.len()];
+            lap.extend((0..20).map(|step| {
+                let t = step as f64 / 20.0;
+                (x1 + (x2 - x1) * t, y1 + (y2 - y1) * t)
+            }));
+        }
+        let expected = transform(25.0, 8.0, false, (-3000.0, 1500.0));
+        let inverse = fit_similarity(&lap.iter().map(|&p| expected.apply(p)).collect::<Vec<_>>(), &lap, false).unwrap();
+        let leds: Vec<(f64, f64)> = lap.iter().step_by(3).map(|&p| inverse.apply(p)).collect();
+
+        let found = fit_icp(&leds, &lap, 50).unwrap();
+        assert!(rms_error(&found, &leds, &leds.iter().map(|&p| expected.apply(p)).collect::<Vec<_>>()) < 1.0);
+        assert!((found.rotation_degrees() - 25.0).abs() < 0.1, "rotation {}", found.rotation_degrees());
+        assert!(fit_icp(&leds, &[], 50).is_none());
+    }
+}
@@ -0,0 +1,490 @@
+// Native command line: OpenF1 fetching, tools and exporters, then the simulator window
+use std::collections::{BTreeMap, BTreeSet};
+use std::error::Error as StdError;
+use std::path::Path;
+use std::sync::Arc;
+use std::time::Duration;
+
+use f1_led_circuit_master_simulation::colors::{read_overrides, ColorScheme};
+use f1_led_circuit_master_simulation::driver_info::{get_driver_info, DriverInfo, Rgb};
+#[cfg(feature = "export")]
+use f1_led_circuit_master_simulation::firmware::FirmwareExport;
+#[cfg(feature = "gui")]
+use f1_led_circuit_master_simulation::gui::{PlotApp, UiState, APP_NAME};
+use f1_led_circuit_master_simulation::hardware::ColorCorrection;
+#[cfg(feature = "hardware-serial")]
+use f1_led_circuit_master_simulation::hardware::{RecorderOutput, SerialOutput, UdpOutput};
+use f1_led_circuit_master_simulation::layout::{read_layout, TrackLayout};
+use f1_led_circuit_master_simulation::playback::{check_speed, OutputStyle, Playback, PlaybackEvent};
+use f1_led_circuit_master_simulation::sinks::OutputSinks;
+#[cfg(feature = "hardware-serial")]
+use f1_led_circuit_master_simulation::sinks::{DropPolicy, SinkOptions};
+use f1_led_circuit_master_simulation::{calibration, FrameEncoding, VisualizationData};
+
+// Names accepted by `--latency` and `--queue`
+const SINK_NAMES: [&str; 4] = ["gui", "serial", "udp", "record"];
+// Frames a recording may fall behind before the oldest are dropped
+#[cfg(feature = "hardware-serial")]
+const RECORD_QUEUE_FRAMES: usize = 1000;
+
+pub fn run() -> Result<(), Box<dyn StdError>> {
+    let args: Vec<String> = std::env::args().collect();
+
+    // `--resume` reopens the files of the last simulator run unless given again
+    let (saved_data, saved_layout) = saved_sources(&args);
+    let data_path = arg_value(&args, "--data").map(str::to_string).or(saved_data);
+    let layout_path = arg_value(&args, "--layout").map(str::to_string).or(saved_layout);
+
+    let layout = match &layout_path {
+        Some(path) => TrackLayout::load(Path::new(path))?,
+        None => read_layout()?,
+    };
+
+    if let Some(path) = arg_value(&args, "--export-layout") {
+        layout.save(Path::new(path))?;
+        return Ok(());
+    }
+
+    if args.iter().any(|arg| arg == "--mock-board") {
+        return run_mock_board(&args, layout);
+    }
+
+    // Aligns a PCB-space `--layout` to known track positions; aligning to a
+    // reference lap needs OpenF1 and is handled in `fetch_session`
+    if let (Some(path), Some(points_path)) = (arg_value(&args, "--calibrate"), arg_value(&args, "--points")) {
+        let points = calibration::read_reference_points(Path::new(points_path))?;
+        let report = calibration::calibrate_with_points(&layout, &points, args.iter().any(|arg| arg == "--affine"))?;
+        print!("{}", report.summary());
+        report.transform.apply_to_layout(&layout).save(Path::new(path))?;
+        return Ok(());
+    }
+
+    // `--colors <scheme>` and `--color-overrides <file>` recolour the simulator,
+    // the hardware outputs and the exports
+    let color_scheme = arg_value(&args, "--colors")
+        .map(|name| {
+            ColorScheme::from_name(name).ok_or_else(|| {
+                let names: Vec<&str> = ColorScheme::ALL.iter().map(|scheme| scheme.name()).collect();
+                format!("Unknown colour scheme {}, expected one of {}", name, names.join(", "))
+            })
+        })
+        .transpose()?;
+    let color_overrides = match arg_value(&args, "--color-overrides") {
+        Some(path) => read_overrides(Path::new(path))?,
+        None => BTreeMap::new(),
+    };
+    // Colour correction for hardware outputs: a JSON profile, with the most
+    // common settings also available as flags
+    let mut correction = match arg_value(&args, "--led-profile") {
+        Some(path) => ColorCorrection::load(Path::new(path))?,
+        None => ColorCorrection::default(),
+    };
+    if let Some(gamma) = arg_value(&args, "--gamma") {
+        correction.gamma = gamma.parse()?;
+    }
+    if let Some(brightness) = arg_value(&args, "--brightness") {
+        correction.brightness = brightness.parse()?;
+    }
+    if let Some(max_current) = arg_value(&args, "--max-current") {
+        correction.max_current_ma = Some(max_current.parse()?);
+    }
+    let base_driver_info = get_driver_info();
+    let driver_info = color_scheme.unwrap_or_default().apply(&base_driver_info, &color_overrides);
+    let data = match &data_path {
+        Some(path) => VisualizationData::load(Path::new(path))?,
+        None => match fetch_session(&args, &layout, &driver_info)? {
+            Some(data) => data,
+            None => return Ok(()), // A layout or diagnostics tool ran instead
+        },
+    };
+
+    #[cfg(feature = "export")]
+    if let Some(dir) = arg_value(&args, "--export-firmware") {
+        let export = FirmwareExport {
+            led_count: match arg_value(&args, "--led-count") {
+                Some(count) => count.parse()?,
+                None => layout.leds.len(),
+            },
+            keyframe_interval: arg_value(&args, "--compress").map(str::parse).transpose()?,
+            driver_info,
+        };
+        export.write(&data, Path::new(dir))?;
+        return Ok(());
+    }
+
+    if let Some(path) = arg_value(&args, "--export") {
+        let mut data = data;
+        // Colours follow `--colors` even when re-exporting a saved session
+        if let Some(metadata) = &mut data.metadata {
+            for entry in &mut metadata.drivers {
+                if let Some(info) = driver_info.iter().find(|d| d.number == entry.driver_number) {
+                    entry.color = Some(info.color);
+                }
+            }
+        }
+        if args.iter().any(|arg| arg == "--no-positions") {
+            data.frames.clear_positions();
+        }
+        if let Some(interval) = arg_value(&args, "--delta") {
+            let keyframe_interval = interval.parse()?;
+            let full_size = serde_json::to_vec(&data)?.len();
+            data.encoding = FrameEncoding::Delta { keyframe_interval };
+            let delta_size = serde_json::to_vec(&data)?.len();
+            println!(
+                "{} frames: {} bytes full, {} bytes delta ({:.1}% smaller)",
+                data.frames.len(),
+                full_size,
+                delta_size,
+                100.0 * (1.0 - delta_size as f64 / full_size.max(1) as f64)
+            );
+        }
+        data.save(Path::new(path))?;
+        return Ok(());
+    }
+
+    let latencies = sink_settings(&args, "--latency")?;
+    let outputs = open_outputs(&args, &latencies)?;
+    if args.iter().any(|arg| arg == "--headless") {
+        let speed = match arg_value(&args, "--speed") {
+            Some(speed) => check_speed(speed.parse()?)?,
+            None => 1.0,
+        };
+        return play_headless(data, layout, driver_info, outputs, correction, speed);
+    }
+    let options = GuiOptions {
+        display_latency: Duration::from_millis(latencies.get("gui").copied().unwrap_or(0)),
+        data_path,
+        layout_path,
+        color_scheme,
+        color_overrides,
+        correction,
+    };
+    run_gui(data, layout, base_driver_info, outputs, options)
+}
+
+// Command line choices handed to the simulator window
+#[cfg_attr(not(feature = "gui"), allow(dead_code))]
+struct GuiOptions {
+    data_path: Option<String>,
+    layout_path: Option<String>,
+    color_scheme: Option<ColorScheme>,
+    color_overrides: BTreeMap<u32, Rgb>,
+    correction: ColorCorrection,
+    display_latency: Duration,
+}
+
+#[cfg(feature = "gui")]
+fn saved_sources(args: &[String]) -> (Option<String>, Option<String>) {
+    if !args.iter().any(|arg| arg == "--resume") {
+        return (None, None);
+    }
+    match UiState::load_saved() {
+        Some(state) => (state.data_source, state.layout_source),
+        None => {
+            eprintln!("No saved simulator state to resume");
+            (None, None)
+        }
+    }
+}
+
+#[cfg(not(feature = "gui"))]
+fn saved_sources(_args: &[String]) -> (Option<String>, Option<String>) {
+    (None, None)
+}
+
+// Fetches and maps the session from OpenF1, or runs one of the tools built
+// on it and returns `None`
+#[cfg(feature = "openf1")]
+fn fetch_session(
+    args: &[String],
+    layout: &TrackLayout,
+    driver_info: &[DriverInfo],
+) -> Result<Option<VisualizationData>, Box<dyn StdError>> {
+    use f1_led_circuit_master_simulation::openf1::{
+        fetch_data, fetch_driver_numbers, fetch_pit_stops, fetch_reference_lap, fetch_session_metadata,
+        SESSION_KEY,
+    };
+    use f1_led_circuit_master_simulation::session::{roster, SessionMetadata};
+    use f1_led_circuit_master_simulation::{diagnostics, generate_update_frames, layout_gen};
+
+    // Initialize the runtime for async execution
+    let runtime = tokio::runtime::Runtime::new()?;
+
+    if let Some(path) = arg_value(args, "--generate-layout") {
+        let driver_number: u32 = arg_value(args, "--driver").unwrap_or("1").parse()?;
+        let lap_number: u32 = arg_value(args, "--lap").unwrap_or("2").parse()?;
+        let led_count: usize = arg_value(args, "--leds").unwrap_or("96").parse()?;
+        let start_offset: f64 = arg_value(args, "--start-offset").unwrap_or("0").parse()?;
+
+        let lap = runtime.block_on(fetch_reference_lap(SESSION_KEY, driver_number, lap_number))?;
+        let polyline = layout_gen::build_polyline(&lap);
+        let leds = layout_gen::generate_leds(&polyline, led_count, start_offset);
+        let mut generated = TrackLayout::from_coordinates(&format!("generated-{}", led_count), &layout.circuit, leds);
+        generated.start_finish_led = Some(1);
+        generated.outline = polyline;
+        generated.save(Path::new(path))?;
+        return Ok(None);
+    }
+
+    // Aligns a PCB-space `--layout` to a reference lap
+    if let Some(path) = arg_value(args, "--calibrate") {
+        let driver_number: u32 = arg_value(args, "--driver").unwrap_or("1").parse()?;
+        let lap_number: u32 = arg_value(args, "--lap").unwrap_or("2").parse()?;
+        let lap = runtime.block_on(fetch_reference_lap(SESSION_KEY, driver_number, lap_number))?;
+        let polyline = layout_gen::build_polyline(&lap);
+        let report = calibration::calibrate_with_lap(layout, &polyline)?;
+        print!("{}", report.summary());
+        let mut calibrated = report.transform.apply_to_layout(layout);
+        calibrated.outline = polyline; // The lap is already in track space
+        calibrated.save(Path::new(path))?;
+        return Ok(None);
+    }
+
+    let driver_numbers = match arg_value(args, "--drivers") {
+        Some(list) => list
+            .split(',')
+            .map(|number| number.trim().parse())
+            .collect::<Result<Vec<u32>, _>>()?,
+        None => runtime.block_on(fetch_driver_numbers(SESSION_KEY))?,
+    };
+    let (raw_data, skipped) = runtime.block_on(fetch_data(&driver_numbers))?;
+    let pit_stops = runtime.block_on(fetch_pit_stops(SESSION_KEY))?;
+
+    if let Some(path) = arg_value(args, "--diagnostics") {
+        let distance_threshold: f64 = arg_value(args, "--snap-threshold").unwrap_or("250").parse()?;
+        let gap_threshold: f64 = arg_value(args, "--gap-threshold").unwrap_or("1.0").parse()?;
+        let report = diagnostics::MappingReport::build(
+            SESSION_KEY,
+            &raw_data,
+            layout,
+            &pit_stops,
+            &driver_numbers,
+            distance_threshold,
+            gap_threshold,
+        )?
+        .with_skipped_records(&skipped);
+        print!("{}", report.summary());
+        report.save(Path::new(path))?;
+        return Ok(None);
+    }
+
+    let mut metadata = runtime
+        .block_on(fetch_session_metadata(SESSION_KEY))
+        .unwrap_or_else(|e| {
+            eprintln!("Failed to fetch session metadata: {}", e);
+            SessionMetadata {
+                session_key: SESSION_KEY.to_string(),
+                ..SessionMetadata::default()
+            }
+        });
+    metadata.session_start = raw_data.get(0).map(|d| d.date);
+    metadata.layout_id = layout.id.clone();
+    metadata.drivers = roster(&driver_numbers, driver_info);
+
+    Ok(Some(VisualizationData {
+        update_rate_ms: 100, // Assuming update rate is 100 ms as in the previous code
+        frames: generate_update_frames(&raw_data, layout, &pit_stops)?,
+        metadata: Some(metadata),
+        encoding: FrameEncoding::Full,
+    }))
+}
+
+#[cfg(not(feature = "openf1"))]
+fn fetch_session(
+    _args: &[String],
+    _layout: &TrackLayout,
+    _driver_info: &[DriverInfo],
+) -> Result<Option<VisualizationData>, Box<dyn StdError>> {
+    Err("Built without the `openf1` feature; pass --data with an exported file".into())
+}
+
+// Hardware boards and recorders mirroring the simulation, from `--serial
+// <port>`, `--udp <host:port>` and `--record <file>`. `--latency` and
+// `--queue` take `<sink>=<value>` lists, e.g. `--latency gui=20,serial=45`.
+#[cfg(feature = "hardware-serial")]
+fn open_outputs(args: &[String], latencies: &BTreeMap<String, u64>) -> Result<OutputSinks, Box<dyn StdError>> {
+    let queues = sink_settings(args, "--queue")?;
+    let options = |name: &str, policy: DropPolicy| SinkOptions {
+        latency: Duration::from_millis(latencies.get(name).copied().unwrap_or(0)),
+        policy: queues.get(name).map_or(policy, |&frames| DropPolicy::Queue(frames as usize)),
+    };
+    let mut outputs = OutputSinks::new();
+
+    if let Some(port) = arg_value(args, "--serial") {
+        let baud_rate: u32 = arg_value(args, "--baud").unwrap_or("115200").parse()?;
+        let output = SerialOutput::open(port, baud_rate)?;
+        outputs.add("serial", Box::new(output), options("serial", DropPolicy::Latest));
+    }
+    if let Some(addr) = arg_value(args, "--udp") {
+        outputs.add("udp", Box::new(UdpOutput::connect(addr)?), options("udp", DropPolicy::Latest));
+    }
+    // A recording should have every frame, so it queues rather than skips
+    if let Some(path) = arg_value(args, "--record") {
+        let output = RecorderOutput::create(Path::new(path))?;
+        outputs.add("record", Box::new(output), options("record", DropPolicy::Queue(RECORD_QUEUE_FRAMES)));
+    }
+
+    Ok(outputs)
+}
+
+#[cfg(not(feature = "hardware-serial"))]
+fn open_outputs(args: &[String], _latencies: &BTreeMap<String, u64>) -> Result<OutputSinks, Box<dyn StdError>> {
+    match ["--serial", "--udp", "--record"].into_iter().find(|flag| arg_value(args, flag).is_some()) {
+        Some(flag) => Err(format!("Built without the `hardware-serial` feature, cannot use {}", flag).into()),
+        None => Ok(OutputSinks::new()),
+    }
+}
+
+// `<sink>=<number>` pairs following `flag`, comma separated
+fn sink_settings(args: &[String], flag: &str) -> Result<BTreeMap<String, u64>, Box<dyn StdError>> {
+    let Some(list) = arg_value(args, flag) else {
+        return Ok(BTreeMap::new());
+    };
+    list.split(',')
+        .map(|pair| {
+            let (name, value) = pair
+                .split_once('=')
+                .ok_or_else(|| format!("Expected <sink>=<number> in {}, got {}", flag, pair))?;
+            let name = name.trim();
+            if !SINK_NAMES.contains(&name) {
+                return Err(format!("Unknown sink {} in {}, expected one of {}", name, flag, SINK_NAMES.join(", ")).into());
+            }
+            Ok((name.to_string(), value.trim().parse()?))
+        })
+        .collect()
+}
+
+// Plays the session to the outputs without a window, until the last frame
+fn play_headless(
+    data: VisualizationData,
+    layout: TrackLayout,
+    driver_info: Vec<DriverInfo>,
+    outputs: OutputSinks,
+    correction: ColorCorrection,
+    speed: f64,
+) -> Result<(), Box<dyn StdError>> {
+    if outputs.is_empty() {
+        return Err("--headless needs an output (--serial, --udp or --record)".into());
+    }
+    let frame_count = data.frames.len();
+    let playback = Playback::new(data.update_rate_ms, Arc::new(data.frames), layout);
+    playback.set_style(OutputStyle {
+        driver_info,
+        hidden_drivers: BTreeSet::new(),
+        correction,
+    });
+    playback.set_outputs(outputs);
+    playback.set_speed(speed)?;
+
+    let events = playback.subscribe();
+    playback.start();
+    for event in events {
+        match event {
+            // A line every 10% of the session
+            PlaybackEvent::Frame { index, race_time } if index % (frame_count / 10).max(1) == 0 => {
+                let percent = 100.0 * index as f64 / frame_count as f64;
+                println!("{:5.1}% frame {} of {}, {:.1} s", percent, index, frame_count, race_time);
+            }
+            PlaybackEvent::Frame { .. } => {}
+            PlaybackEvent::Finished => break,
+        }
+    }
+
+    for (name, _, stats) in playback.output_stats() {
+        println!("{}: {} sent, {} dropped, {} failed", name, stats.sent, stats.dropped, stats.errors);
+    }
+    Ok(())
+}
+
+// Emulated LED board for testing outputs without hardware: a serial
+// pseudo-terminal and a UDP socket, shown in its own window
+#[cfg(feature = "hardware-serial")]
+fn run_mock_board(args: &[String], layout: TrackLayout) -> Result<(), Box<dyn StdError>> {
+    use f1_led_circuit_master_simulation::mock_board::MockBoard;
+
+    let mut board = MockBoard::new();
+    #[cfg(unix)]
+    println!("Serial: {}", board.open_serial()?);
+    let addr = board.listen_udp(arg_value(args, "--udp-listen").unwrap_or("127.0.0.1:7777"))?;
+    println!("UDP: {}", addr);
+
+    #[cfg(feature = "gui")]
+    {
+        use f1_led_circuit_master_simulation::mock_board::MockBoardApp;
+        eframe::run_native(
+            "MOCK LED BOARD",
+            eframe::NativeOptions::default(),
+            Box::new(move |_| Box::new(MockBoardApp::new(board, layout))),
+        )?;
+        Ok(())
+    }
+    // Without a window, print a line per frame until interrupted
+    #[cfg(not(feature = "gui"))]
+    {
+        let _ = layout;
+        let mut seen = 0;
+        loop {
+            let frames = board.wait_for_frames(seen + 1, std::time::Duration::from_secs(1));
+            let start = frames.partition_point(|frame| frame.index < seen);
+            for frame in &frames[start..] {
+                let lit = frame.colors.iter().filter(|&&color| color != Rgb::BLACK).count();
+                println!("{:?}: {} LEDs, {} lit", frame.source, frame.colors.len(), lit);
+            }
+            seen = frames.last().map_or(seen, |frame| frame.index + 1);
+        }
+    }
+}
+
+#[cfg(not(feature = "hardware-serial"))]
+fn run_mock_board(_args: &[String], _layout: TrackLayout) -> Result<(), Box<dyn StdError>> {
+    Err("Built without the `hardware-serial` feature, which brings the mock board".into())
+}
+
+#[cfg(feature = "gui")]
+fn run_gui(
+    data: VisualizationData,
+    layout: TrackLayout,
+    driver_info: Vec<DriverInfo>,
+    outputs: OutputSinks,
+    options: GuiOptions,
+) -> Result<(), Box<dyn StdError>> {
+    let mut app = PlotApp::new(data.update_rate_ms, data.frames, layout, driver_info);
+    // Absolute, so `--resume` works from any directory
+    let absolute = |path: String| std::fs::canonicalize(&path).map_or(path, |p| p.display().to_string());
+    app.set_sources(options.data_path.map(absolute), options.layout_path.map(absolute));
+    app.set_color_correction(options.correction);
+    app.set_display_latency(options.display_latency);
+    app.set_outputs(outputs);
+
+    let native_options = eframe::NativeOptions::default();
+    eframe::run_native(
+        APP_NAME,
+        native_options,
+        Box::new(move |cc| {
+            app.restore_state(UiState::restore(cc.storage));
+            app.set_colors(options.color_scheme, options.color_overrides);
+            Box::new(app)
+        }),
+    )?;
+
+    Ok(())
+}
+
+#[cfg(not(feature = "gui"))]
+fn run_gui(
+    _data: VisualizationData,
+    _layout: TrackLayout,
+    _driver_info: Vec<DriverInfo>,
+    _outputs: OutputSinks,
+    _options: GuiOptions,
+) -> Result<(), Box<dyn StdError>> {
+    Err("Built without the `gui` feature; use one of the export options".into())
+}
+
+// Value following a `--flag` on the command line
+fn arg_value<'a>(args: &'a [String], flag: &str) -> Option<&'a str> {
+    args.iter()
+        .position(|arg| arg == flag)
+        .and_then(|index| args.get(index + 1))
+        .map(|value| value.as_str())
+}
@@ -0,0 +1,194 @@
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::error::Error as StdError;
+use std::fs;
+use std::path::Path;
+
+use crate::driver_info::{DriverInfo, Rgb};
+
+// Kelly's colours of maximum contrast, without black (an unlit LED) and the
+// near-black olive
+const HIGH_CONTRAST: [Rgb; 20] = [
+    Rgb::from_rgb(0xF2, 0xF3, 0xF4),
+    Rgb::from_rgb(0xF3, 0xC3, 0x00),
+    Rgb::from_rgb(0x87, 0x56, 0x92),
+    Rgb::from_rgb(0xF3, 0x84, 0x00),
+    Rgb::from_rgb(0xA1, 0xCA, 0xF1),
+    Rgb::from_rgb(0xBE, 0x00, 0x32),
+    Rgb::from_rgb(0xC2, 0xB2, 0x80),
+    Rgb::from_rgb(0x84, 0x84, 0x82),
+    Rgb::from_rgb(0x00, 0x88, 0x56),
+    Rgb::from_rgb(0xE6, 0x8F, 0xAC),
+    Rgb::from_rgb(0x00, 0x67, 0xA5),
+    Rgb::from_rgb(0xF9, 0x93, 0x79),
+    Rgb::from_rgb(0x60, 0x4E, 0x97),
+    Rgb::from_rgb(0xF6, 0xA6, 0x00),
+    Rgb::from_rgb(0xB3, 0x44, 0x6C),
+    Rgb::from_rgb(0xDC, 0xD3, 0x00),
+    Rgb::from_rgb(0x88, 0x2D, 0x17),
+    Rgb::from_rgb(0x8D, 0xB6, 0x00),
+    Rgb::from_rgb(0x65, 0x45, 0x22),
+    Rgb::from_rgb(0xE2, 0x58, 0x22),
+];
+
+// Paul Tol's muted scheme plus its pale grey, distinguishable with the
+// common forms of colour blindness; one colour per team
+const COLOR_BLIND_SAFE: [Rgb; 10] = [
+    Rgb::from_rgb(0x88, 0xCC, 0xEE),
+    Rgb::from_rgb(0x44, 0xAA, 0x99),
+    Rgb::from_rgb(0x11, 0x77, 0x33),
+    Rgb::from_rgb(0x99, 0x99, 0x33),
+    Rgb::from_rgb(0xDD, 0xCC, 0x77),
+    Rgb::from_rgb(0xCC, 0x66, 0x77),
+    Rgb::from_rgb(0x88, 0x22, 0x55),
+    Rgb::from_rgb(0xAA, 0x44, 0x99),
+    Rgb::from_rgb(0x33, 0x22, 0x88),
+    Rgb::from_rgb(0xDD, 0xDD, 0xDD),
+];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum ColorScheme {
+    // Team colours, team-mates after the first get a lighter or darker shade
+    #[default]
+    Team,
+    // A distinct colour for every driver
+    HighContrast,
+    // Colour-blind safe team colours, team-mates shaded as in `Team`
+    ColorBlind,
+}
+
+impl ColorScheme {
+    pub const ALL: [ColorScheme; 3] = [ColorScheme::Team, ColorScheme::HighContrast, ColorScheme::ColorBlind];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            ColorScheme::Team => "team",
+            ColorScheme::HighContrast => "high-contrast",
+            ColorScheme::ColorBlind => "color-blind",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<ColorScheme> {
+        ColorScheme::ALL.into_iter().find(|scheme| scheme.name() == name)
+    }
+
+    // Driver info recoloured with this scheme, then the per-driver overrides
+    pub fn apply(self, driver_info: &[DriverInfo], overrides: &BTreeMap<u32, Rgb>) -> Vec<DriverInfo> {
+        let mut teams: Vec<&str> = Vec::new();
+        let mut seen_in_team: BTreeMap<&str, usize> = BTreeMap::new();
+
+        driver_info
+            .iter()
+            .enumerate()
+            .map(|(index, driver)| {
+                if !teams.contains(&driver.team) {
+                    teams.push(driver.team);
+                }
+                let team_index = teams.iter().position(|&team| team == driver.team).unwrap_or(0);
+                let seat = seen_in_team.entry(driver.team).or_insert(0);
+                let team_mate = *seat > 0;
+                *seat += 1;
+
+                let color = match self {
+                    ColorScheme::Team => shade(driver.color, team_mate),
+                    ColorScheme::HighContrast => high_contrast(index),
+                    ColorScheme::ColorBlind => {
+                        shade(COLOR_BLIND_SAFE[team_index % COLOR_BLIND_SAFE.len()], team_mate)
+                    }
+                };
+                DriverInfo {
+                    color: overrides.get(&driver.number).copied().unwrap_or(color),
+                    ..driver.clone()
+                }
+            })
+            .collect()
+    }
+}
+
+// The `index`th high contrast colour. Kelly's list runs out at 20, so larger
+// grids continue with saturated hues a golden angle apart, which never repeat.
+fn high_contrast(index: usize) -> Rgb {
+    if let Some(&color) = HIGH_CONTRAST.get(index) {
+        return color;
+    }
+    let hue = ((index - HIGH_CONTRAST.len()) as f32 * 0.618_034 + 0.1).fract() * 6.0;
+    let (value, chroma) = (0.95, 0.95 * 0.8);
+    let x = chroma * (1.0 - (hue % 2.0 - 1.0).abs());
+    let (r, g, b) = match hue as u32 {
+        0 => (chroma, x, 0.0),
+        1 => (x, chroma, 0.0),
+        2 => (0.0, chroma, x),
+        3 => (0.0, x, chroma),
+        4 => (x, 0.0, chroma),
+        _ => (chroma, 0.0, x),
+    };
+    let channel = |c: f32| ((c + value - chroma) * 255.0).round() as u8;
+    Rgb::from_rgb(channel(r), channel(g), channel(b))
+}
+
+// Secondary shade for a team-mate: dark colours are lightened and light ones
+// darkened, far enough apart to tell the two LEDs apart
+fn shade(color: Rgb, team_mate: bool) -> Rgb {
+    if !team_mate {
+        color
+    } else if color.luminance() > 0.6 {
+        color.scaled(0.5)
+    } else {
+        color.mix(Rgb::WHITE, 0.55)
+    }
+}
+
+// Per-driver colours from a JSON object such as `{ "16": "#DC0000" }`
+pub fn read_overrides(path: &Path) -> Result<BTreeMap<u32, Rgb>, Box<dyn StdError>> {
+    let file = fs::File::open(path)?;
+    Ok(serde_json::from_reader(file)?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::driver_info::get_driver_info;
+    use std::collections::BTreeSet;
+
+    #[test]
+    fn team_mates_differ_in_every_scheme() {
+        let drivers = get_driver_info();
+        for scheme in ColorScheme::ALL {
+            let colored = scheme.apply(&drivers, &BTreeMap::new());
+            for (i, a) in colored.iter().enumerate() {
+                for b in &colored[i + 1..] {
+                    if a.team == b.team {
+                        assert_ne!(a.color, b.color, "{} and {} in {}", a.number, b.number, scheme.name());
+                    }
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn overrides_win() {
+        let drivers = get_driver_info();
+        let red = Rgb::from_rgb(0xDC, 0, 0);
+        let overrides = BTreeMap::from([(drivers[3].number, red)]);
+        for scheme in ColorScheme::ALL {
+            let colored = scheme.apply(&drivers, &overrides);
+            assert_eq!(colored[3].color, red);
+            // Nobody else changes
+            assert_eq!(colored[4].color, scheme.apply(&drivers, &BTreeMap::new())[4].color);
+        }
+    }
+
+    #[test]
+    fn high_contrast_never_repeats_on_a_large_grid() {
+        let colors: BTreeSet<(u8, u8, u8)> = (0..64).map(high_contrast).map(|c| (c.r, c.g, c.b)).collect();
+        assert_eq!(colors.len(), 64);
+        assert!((0..64).map(high_contrast).all(|c| c.luminance() > 0.1)); // Never close to an unlit LED
+
+        let grid: Vec<DriverInfo> = (0..22)
+            .map(|i| DriverInfo { number: i + 1, name: "Driver", team: "Team", color: Rgb::WHITE })
+            .collect();
+        let colored = ColorScheme::HighContrast.apply(&grid, &BTreeMap::new());
+        assert_eq!(colored.iter().map(|d| (d.color.r, d.color.g, d.color.b)).collect::<BTreeSet<_>>().len(), 22);
+    }
+}
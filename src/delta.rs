@@ -0,0 +1,190 @@
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+
+use crate::frames::{DriverData, RawPosition, UpdateFrame};
+
+pub const DEFAULT_KEYFRAME_INTERVAL: usize = 50;
+
+// A keyframe holds every car's position, other frames only the cars whose
+// LED or pit state changed since the previous frame and the cars that left
+// it. Keyframes reset the decoder state, so playback can seek to any of them.
+// Raw positions change every frame and are stored as they are.
+//
+// Frames are normalised before encoding (see `normalize`), which is what
+// decoding gives back: `decode(encode(frames))` equals `normalize(frames)`.
+// A `FrameStore` only holds normalised frames, so a session plays the same
+// from either encoding.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DeltaFrame {
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub keyframe: bool,
+    #[serde(deserialize_with = "crate::frames::deserialize_drivers")]
+    pub drivers: Vec<DriverData>,
+    #[serde(default)]
+    pub session_time: f64,
+    #[serde(default)]
+    pub date: Option<DateTime<Utc>>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub removed: Vec<u32>, // Driver numbers of cars in the previous frame but not this one
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub positions: Vec<RawPosition>,
+}
+
+// One entry per car, ordered by driver number; of several entries for one
+// car the last wins. Generated frames hold a grid's worth of samples, so a
+// car can be in a frame twice.
+pub fn normalize_drivers(drivers: &[DriverData]) -> Vec<DriverData> {
+    let mut sorted = drivers.to_vec();
+    sorted.sort_by_key(|driver| driver.driver_number); // Stable, so the last entry stays last
+    let mut normalized: Vec<DriverData> = Vec::with_capacity(sorted.len());
+    for driver in sorted {
+        match normalized.last_mut() {
+            Some(last) if last.driver_number == driver.driver_number => *last = driver,
+            _ => normalized.push(driver),
+        }
+    }
+    normalized
+}
+
+// Frames with their cars normalised (see `normalize_drivers`)
+pub fn normalize<I>(frames: I) -> impl Iterator<Item = UpdateFrame>
+where
+    I: IntoIterator<Item = UpdateFrame>,
+{
+    frames.into_iter().map(|frame| UpdateFrame {
+        drivers: normalize_drivers(&frame.drivers),
+        ..frame
+    })
+}
+
+// Frames are encoded one at a time, so a stored session never needs a second copy
+pub fn encode<I>(frames: I, keyframe_interval: usize) -> impl Iterator<Item = DeltaFrame>
+where
+    I: IntoIterator<Item = UpdateFrame>,
+{
+    let keyframe_interval = keyframe_interval.max(1);
+    let mut previous: BTreeMap<u32, DriverData> = BTreeMap::new();
+
+    normalize(frames).enumerate().map(move |(index, frame)| {
+        let keyframe = index % keyframe_interval == 0;
+        // Both lists are in driver order
+        let removed: Vec<u32> = previous
+            .keys()
+            .copied()
+            .filter(|number| frame.drivers.binary_search_by_key(number, |driver| driver.driver_number).is_err())
+            .collect();
+        for number in &removed {
+            previous.remove(number);
+        }
+        let changed: Vec<DriverData> = frame
+            .drivers
+            .iter()
+            .filter(|driver| previous.insert(driver.driver_number, **driver) != Some(**driver))
+            .copied()
+            .collect();
+
+        DeltaFrame {
+            keyframe,
+            drivers: if keyframe { frame.drivers } else { changed },
+            // A keyframe replaces the decoder state anyway
+            removed: if keyframe { Vec::new() } else { removed },
+            session_time: frame.session_time,
+            date: frame.date,
+            positions: frame.positions,
+        }
+    })
+}
+
+impl From<DeltaFrame> for UpdateFrame {
+    // Only meaningful for frames that were stored in full
+    fn from(delta: DeltaFrame) -> UpdateFrame {
+        UpdateFrame {
+            drivers: delta.drivers,
+            session_time: delta.session_time,
+            date: delta.date,
+            positions: delta.positions,
+        }
+    }
+}
+
+// Full frames with the latest position of every car still in the session
+pub fn decode<I>(deltas: I) -> impl Iterator<Item = UpdateFrame>
+where
+    I: IntoIterator<Item = DeltaFrame>,
+{
+    let mut state: BTreeMap<u32, DriverData> = BTreeMap::new();
+    deltas.into_iter().map(move |delta| {
+        if delta.keyframe {
+            state.clear();
+        }
+        for number in &delta.removed {
+            state.remove(number);
+        }
+        for driver in &delta.drivers {
+            state.insert(driver.driver_number, *driver);
+        }
+
+        UpdateFrame {
+            drivers: state.values().copied().collect(),
+            session_time: delta.session_time,
+            date: delta.date,
+            positions: delta.positions,
+        }
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn frame(drivers: &[(u32, usize)]) -> UpdateFrame {
+        UpdateFrame {
+            drivers: drivers
+                .iter()
+                .map(|&(driver_number, led_num)| DriverData { driver_number, led_num, pit_duration: None })
+                .collect(),
+            ..UpdateFrame::default()
+        }
+    }
+
+    #[test]
+    fn decoding_gives_back_normalised_frames() {
+        // Car 44 twice in one frame, car 1 missing from another, car 81 late
+        let frames = vec![
+            frame(&[(44, 3), (1, 5), (44, 4)]),
+            frame(&[(44, 5)]),
+            frame(&[(1, 6), (81, 1)]),
+            frame(&[(81, 2), (1, 6), (44, 5)]),
+            frame(&[]),
+        ];
+        let normalised: Vec<UpdateFrame> = normalize(frames.clone()).collect();
+        assert_eq!(normalised[0], frame(&[(1, 5), (44, 4)]));
+        assert_eq!(normalised[1], frame(&[(44, 5)]));
+        assert_eq!(normalised[3], frame(&[(1, 6), (44, 5), (81, 2)]));
+        assert_eq!(normalised[4], frame(&[]));
+
+        for keyframe_interval in [1, 2, 3, 50] {
+            let decoded: Vec<UpdateFrame> = decode(encode(frames.clone(), keyframe_interval)).collect();
+            assert_eq!(decoded, normalised);
+        }
+    }
+
+    #[test]
+    fn cars_that_leave_are_removed() {
+        let frames = vec![frame(&[(1, 5), (44, 3)]), frame(&[(1, 6)]), frame(&[(1, 7)]), frame(&[(1, 7), (44, 4)])];
+        let deltas: Vec<DeltaFrame> = encode(frames.clone(), 50).collect();
+        assert_eq!(deltas[1].removed, vec![44]);
+        assert!(deltas[2].removed.is_empty());
+        assert_eq!(deltas[3].drivers, frame(&[(44, 4)]).drivers);
+        assert_eq!(decode(deltas).collect::<Vec<_>>(), frames);
+    }
+
+    #[test]
+    fn normalised_frames_round_trip() {
+        let frames: Vec<UpdateFrame> = normalize((0..20).map(|i| frame(&[(i % 3, i as usize), (7, 1)]))).collect();
+        let deltas: Vec<DeltaFrame> = encode(frames.clone(), 4).collect();
+        assert_eq!(deltas[1].drivers, frame(&[(1, 1)]).drivers); // Only the car that moved
+        assert_eq!(decode(deltas).collect::<Vec<_>>(), frames);
+    }
+}
@@ -0,0 +1,380 @@
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+use std::collections::BTreeMap;
+use std::error::Error as StdError;
+use std::fs;
+use std::path::Path;
+
+use crate::frames::LedMapper;
+use crate::layout::TrackLayout;
+use crate::location_parser::SkippedRecords;
+use crate::pit::PitStop;
+use crate::samples::LocationSamples;
+
+const HISTOGRAM_BUCKETS: usize = 10;
+const MAX_LISTED_OUTLIERS: usize = 100;
+const HEATMAP_SHADES: &[u8] = b" .:-=+*#%@"; // No hits, then up to the busiest LED
+const HEATMAP_WIDTH: usize = 48; // LEDs per line
+
+#[derive(Debug, Default, Serialize)]
+pub struct DistanceStats {
+    pub min: f64,
+    pub mean: f64,
+    pub median: f64,
+    pub p95: f64,
+    pub max: f64,
+}
+
+#[derive(Debug, Serialize)]
+pub struct HistogramBucket {
+    pub upper_bound: Option<f64>, // None for the open-ended last bucket
+    pub count: usize,
+}
+
+#[derive(Debug, Serialize)]
+pub struct OutlierSample {
+    pub driver_number: u32,
+    pub date: DateTime<Utc>,
+    pub x: f64,
+    pub y: f64,
+    pub led_num: usize,
+    pub distance: f64,
+}
+
+#[derive(Debug, Serialize)]
+pub struct TimestampGap {
+    pub start: DateTime<Utc>,
+    pub end: DateTime<Utc>,
+    pub seconds: f64,
+}
+
+#[derive(Debug, Serialize)]
+pub struct DriverDiagnostics {
+    pub driver_number: u32,
+    pub sample_count: usize,
+    pub first_sample: Option<DateTime<Utc>>,
+    pub last_sample: Option<DateTime<Utc>>,
+    pub gaps: Vec<TimestampGap>, // Intervals without samples longer than the gap threshold
+    pub unreadable_records: usize, // Left out of the OpenF1 response
+}
+
+#[derive(Debug, Serialize)]
+pub struct MappingReport {
+    pub session_key: String,
+    pub layout_id: String,
+    pub sample_count: usize,
+    pub distance_threshold: f64,
+    pub gap_threshold_seconds: f64,
+    pub snap_distance: DistanceStats,
+    pub histogram: Vec<HistogramBucket>,
+    pub samples_beyond_threshold: usize,
+    pub worst_samples: Vec<OutlierSample>, // Furthest samples beyond the threshold
+    pub leds_never_hit: Vec<usize>,
+    pub led_hits: BTreeMap<usize, usize>,
+    pub drivers: Vec<DriverDiagnostics>,
+    pub drivers_without_data: Vec<u32>,
+}
+
+impl MappingReport {
+    pub fn build(
+        session_key: &str,
+        raw_data: &LocationSamples,
+        layout: &TrackLayout,
+        pit_stops: &[PitStop],
+        driver_numbers: &[u32],
+        distance_threshold: f64,
+        gap_threshold_seconds: f64,
+    ) -> Result<MappingReport, Box<dyn StdError>> {
+        // The histogram is bucketed in fractions of it
+        if !(distance_threshold > 0.0 && distance_threshold.is_finite()) {
+            return Err(format!("Snap distance threshold must be positive, got {}", distance_threshold).into());
+        }
+        if gap_threshold_seconds.is_nan() || gap_threshold_seconds < 0.0 {
+            return Err(format!("Gap threshold must not be negative, got {}", gap_threshold_seconds).into());
+        }
+        let mapper = LedMapper::new(layout, pit_stops);
+
+        let mut distances = Vec::with_capacity(raw_data.len());
+        let mut outliers = Vec::new();
+        let mut led_hits: BTreeMap<usize, usize> =
+            layout.leds.iter().map(|coord| (coord.led_number, 0)).collect();
+        let mut dates: BTreeMap<u32, Vec<DateTime<Utc>>> = BTreeMap::new();
+
+        for data in raw_data.iter() {
+            // Gaps count every sample, mapped or not
+            dates.entry(data.driver_number).or_default().push(data.date);
+            let Some((driver_data, distance)) = mapper.map(&data) else {
+                continue;
+            };
+            distances.push(distance);
+            *led_hits.entry(driver_data.led_num).or_insert(0) += 1;
+
+            if distance > distance_threshold {
+                outliers.push(OutlierSample {
+                    driver_number: data.driver_number,
+                    date: data.date,
+                    x: data.x,
+                    y: data.y,
+                    led_num: driver_data.led_num,
+                    distance,
+                });
+            }
+        }
+
+        let samples_beyond_threshold = outliers.len();
+        outliers.sort_by(|a, b| b.distance.partial_cmp(&a.distance).unwrap_or(std::cmp::Ordering::Equal));
+        outliers.truncate(MAX_LISTED_OUTLIERS);
+
+        let leds_never_hit = led_hits
+            .iter()
+            .filter(|(_, &hits)| hits == 0)
+            .map(|(&led_number, _)| led_number)
+            .collect();
+
+        let drivers: Vec<DriverDiagnostics> = driver_numbers
+            .iter()
+            .map(|&driver_number| {
+                let dates = dates.get(&driver_number).map_or(&[][..], Vec::as_slice);
+                driver_diagnostics(driver_number, dates, gap_threshold_seconds)
+            })
+            .collect();
+        let drivers_without_data = drivers
+            .iter()
+            .filter(|driver| driver.sample_count == 0)
+            .map(|driver| driver.driver_number)
+            .collect();
+
+        distances.sort_by(|a, b| a.partial_cmp(b).unwrap_or(std::cmp::Ordering::Equal));
+
+        Ok(MappingReport {
+            session_key: session_key.to_string(),
+            layout_id: layout.id.clone(),
+            sample_count: raw_data.len(),
+            distance_threshold,
+            gap_threshold_seconds,
+            snap_distance: distance_stats(&distances),
+            histogram: histogram(&distances, distance_threshold),
+            samples_beyond_threshold,
+            worst_samples: outliers,
+            leds_never_hit,
+            led_hits,
+            drivers,
+            drivers_without_data,
+        })
+    }
+
+    // Adds the records of each driver that could not be read when fetching
+    pub fn with_skipped_records(mut self, skipped: &BTreeMap<u32, SkippedRecords>) -> MappingReport {
+        for driver in &mut self.drivers {
+            driver.unreadable_records = skipped.get(&driver.driver_number).map_or(0, |skipped| skipped.count);
+        }
+        self
+    }
+
+    pub fn save(&self, path: &Path) -> Result<(), Box<dyn StdError>> {
+        let file = fs::File::create(path)?;
+        serde_json::to_writer_pretty(file, self)?;
+        Ok(())
+    }
+
+    pub fn summary(&self) -> String {
+        let stats = &self.snap_distance;
+        let mut text = format!(
+            "Session {} on layout {}: {} samples\n",
+            self.session_key, self.layout_id, self.sample_count
+        );
+        text.push_str(&format!(
+            "Snap distance: min {:.0}, mean {:.0}, median {:.0}, p95 {:.0}, max {:.0}\n",
+            stats.min, stats.mean, stats.median, stats.p95, stats.max
+        ));
+        text.push_str(&format!(
+            "Beyond {:.0}: {} samples ({:.1}%)\n",
+            self.distance_threshold,
+            self.samples_beyond_threshold,
+            100.0 * self.samples_beyond_threshold as f64 / self.sample_count.max(1) as f64
+        ));
+
+        let max_count = self.histogram.iter().map(|bucket| bucket.count).max().unwrap_or(0).max(1);
+        for bucket in &self.histogram {
+            let bar = "#".repeat(bucket.count * 40 / max_count);
+            let bound = match bucket.upper_bound {
+                Some(upper_bound) => format!("<= {:>6.0}", upper_bound),
+                None => format!(">  {:>6.0}", 2.0 * self.distance_threshold),
+            };
+            text.push_str(&format!("  {} {:>8} {}\n", bound, bucket.count, bar));
+        }
+
+        text.push_str(&format!("LEDs never hit: {:?}\n", self.leds_never_hit));
+        if let Some((led_number, hits)) = self.led_hits.iter().max_by_key(|(_, &hits)| hits) {
+            text.push_str(&format!("Busiest LED: U{} ({} hits)\n", led_number, hits));
+        }
+        text.push_str(&self.heatmap());
+
+        for driver in &self.drivers {
+            let longest = driver.gaps.iter().map(|gap| gap.seconds).fold(0.0, f64::max);
+            text.push_str(&format!(
+                "Driver {}: {} samples, {} gaps (longest {:.1}s)",
+                driver.driver_number,
+                driver.sample_count,
+                driver.gaps.len(),
+                longest
+            ));
+            if driver.unreadable_records > 0 {
+                text.push_str(&format!(", {} unreadable records", driver.unreadable_records));
+            }
+            text.push('\n');
+        }
+        if !self.drivers_without_data.is_empty() {
+            text.push_str(&format!("No data for drivers: {:?}\n", self.drivers_without_data));
+        }
+        text
+    }
+
+    // Hits per LED as lines of shades, a character per LED in board order
+    pub fn heatmap(&self) -> String {
+        let busiest = self.led_hits.values().copied().max().unwrap_or(0).max(1);
+        let levels = HEATMAP_SHADES.len() - 1;
+        let leds: Vec<(usize, char)> = self
+            .led_hits
+            .iter()
+            .map(|(&led_number, &hits)| (led_number, HEATMAP_SHADES[(hits * levels).div_ceil(busiest)] as char))
+            .collect();
+
+        let mut text = format!("LED hits (blank for none, '@' for {}):\n", busiest);
+        for line in leds.chunks(HEATMAP_WIDTH) {
+            let strip: String = line.iter().map(|&(_, shade)| shade).collect();
+            text.push_str(&format!("  U{:<4} |{}|\n", line[0].0, strip));
+        }
+        text
+    }
+}
+
+// Expects `sorted` in ascending order
+fn distance_stats(sorted: &[f64]) -> DistanceStats {
+    if sorted.is_empty() {
+        return DistanceStats::default();
+    }
+
+    let percentile = |p: f64| sorted[((sorted.len() - 1) as f64 * p).round() as usize];
+    DistanceStats {
+        min: sorted[0],
+        mean: sorted.iter().sum::<f64>() / sorted.len() as f64,
+        median: percentile(0.5),
+        p95: percentile(0.95),
+        max: sorted[sorted.len() - 1],
+    }
+}
+
+// Equal width buckets up to twice the threshold, with an open-ended last
+// bucket. Bounds are inclusive, a distance on one falls in the lower bucket.
+fn histogram(sorted: &[f64], distance_threshold: f64) -> Vec<HistogramBucket> {
+    let width = 2.0 * distance_threshold / HISTOGRAM_BUCKETS as f64;
+    let mut buckets: Vec<HistogramBucket> = (1..=HISTOGRAM_BUCKETS)
+        .map(|i| HistogramBucket {
+            upper_bound: Some(width * i as f64),
+            count: 0,
+        })
+        .collect();
+    buckets.push(HistogramBucket {
+        upper_bound: None,
+        count: 0,
+    });
+
+    for &distance in sorted {
+        let index = ((distance / width).ceil() as usize).saturating_sub(1).min(HISTOGRAM_BUCKETS);
+        buckets[index].count += 1;
+    }
+    buckets
+}
+
+// `dates` are the driver's samples, in the order they were fetched
+fn driver_diagnostics(driver_number: u32, dates: &[DateTime<Utc>], gap_threshold_seconds: f64) -> DriverDiagnostics {
+    let gaps = dates
+        .windows(2)
+        .filter_map(|pair| {
+            let seconds = (pair[1] - pair[0]).num_milliseconds() as f64 / 1000.0;
+            (seconds > gap_threshold_seconds).then(|| TimestampGap {
+                start: pair[0],
+                end: pair[1],
+                seconds,
+            })
+        })
+        .collect();
+
+    DriverDiagnostics {
+        driver_number,
+        sample_count: dates.len(),
+        first_sample: dates.first().copied(),
+        last_sample: dates.last().copied(),
+        gaps,
+        unreadable_records: 0,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn distances_on_a_bound_fall_in_the_lower_bucket() {
+        // Buckets of 50 up to 500 with a threshold of 250
+        let counts: Vec<usize> = histogram(&[0.0, 25.0, 50.0, 50.5, 100.0, 500.0, 500.1, 9000.0], 250.0)
+            .iter()
+            .map(|bucket| bucket.count)
+            .collect();
+        assert_eq!(counts, [3, 2, 0, 0, 0, 0, 0, 0, 0, 1, 2]);
+    }
+
+    #[test]
+    fn a_threshold_must_be_positive() {
+        let layout = crate::layout::read_layout().unwrap();
+        for threshold in [0.0, -1.0, f64::NAN] {
+            assert!(MappingReport::build("9149", &LocationSamples::new(), &layout, &[], &[], threshold, 1.0).is_err());
+        }
+        assert!(MappingReport::build("9149", &LocationSamples::new(), &layout, &[], &[], 250.0, 1.0).is_ok());
+    }
+
+    #[test]
+    fn a_gap_threshold_must_not_be_negative() {
+        let layout = crate::layout::read_layout().unwrap();
+        for gap in [-0.5, f64::NAN] {
+            assert!(MappingReport::build("9149", &LocationSamples::new(), &layout, &[], &[], 250.0, gap).is_err());
+        }
+        assert!(MappingReport::build("9149", &LocationSamples::new(), &layout, &[], &[], 250.0, 0.0).is_ok());
+    }
+
+    #[test]
+    fn gaps_are_found_per_driver_and_hits_drawn_per_led() {
+        let layout = crate::layout::read_layout().unwrap();
+        let start = DateTime::from_timestamp(1_693_141_200, 0).unwrap();
+        let at_led = |led: usize, driver_number: u32, millis: i64| {
+            let coord = layout.coordinate(led).unwrap();
+            crate::frames::LocationData {
+                x: coord.x_led,
+                y: coord.y_led,
+                date: start + chrono::Duration::milliseconds(millis),
+                driver_number,
+            }
+        };
+        // Two drivers interleaved; driver 44 misses two seconds
+        let mut samples = LocationSamples::new();
+        for (led, driver_number, millis) in [(1, 1, 0), (1, 44, 100), (1, 1, 300), (2, 44, 2400), (1, 1, 600), (3, 44, 2700)] {
+            samples.push(&at_led(led, driver_number, millis)).unwrap();
+        }
+
+        let report = MappingReport::build("9149", &samples, &layout, &[], &[1, 44, 81], 250.0, 1.0).unwrap();
+        let counts: Vec<(u32, usize, usize)> =
+            report.drivers.iter().map(|d| (d.driver_number, d.sample_count, d.gaps.len())).collect();
+        assert_eq!(counts, [(1, 3, 0), (44, 3, 1), (81, 0, 0)]);
+        assert_eq!(report.drivers[1].gaps[0].seconds, 2.3);
+        assert_eq!(report.drivers_without_data, [81]);
+
+        // LED 1 has four hits, LEDs 2 and 3 one each, out of 96 LEDs
+        let heatmap = report.heatmap();
+        let lines: Vec<&str> = heatmap.lines().collect();
+        assert_eq!(lines[0], "LED hits (blank for none, '@' for 4):");
+        assert_eq!(lines[1], format!("  U1    |@--{}|", " ".repeat(45)));
+        assert_eq!(lines[2], format!("  U49   |{}|", " ".repeat(48)));
+        assert!(report.summary().contains(&heatmap));
+    }
+}
@@ -1,11 +1,78 @@
-use eframe::egui;
+use serde::de::{self, Deserializer};
+use serde::{Deserialize, Serialize, Serializer};
 
-#[derive(Debug)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Rgb {
+    pub r: u8,
+    pub g: u8,
+    pub b: u8,
+}
+
+impl Rgb {
+    pub const BLACK: Rgb = Rgb::from_rgb(0, 0, 0);
+    pub const WHITE: Rgb = Rgb::from_rgb(255, 255, 255);
+
+    pub const fn from_rgb(r: u8, g: u8, b: u8) -> Rgb {
+        Rgb { r, g, b }
+    }
+
+    pub fn scaled(self, factor: f32) -> Rgb {
+        let scale = |channel: u8| (channel as f32 * factor).round().clamp(0.0, 255.0) as u8;
+        Rgb::from_rgb(scale(self.r), scale(self.g), scale(self.b))
+    }
+
+    // Blends towards `other`, 0.0 keeps this colour and 1.0 gives `other`
+    pub fn mix(self, other: Rgb, amount: f32) -> Rgb {
+        let blend = |a: u8, b: u8| (a as f32 + (b as f32 - a as f32) * amount).round().clamp(0.0, 255.0) as u8;
+        Rgb::from_rgb(blend(self.r, other.r), blend(self.g, other.g), blend(self.b, other.b))
+    }
+
+    // Relative luminance in 0.0..=1.0, ignoring gamma
+    pub fn luminance(self) -> f32 {
+        (0.2126 * self.r as f32 + 0.7152 * self.g as f32 + 0.0722 * self.b as f32) / 255.0
+    }
+
+    // `#RRGGBB`, the leading `#` is optional
+    pub fn from_hex(hex: &str) -> Option<Rgb> {
+        let hex = hex.strip_prefix('#').unwrap_or(hex);
+        if hex.len() != 6 || !hex.is_ascii() {
+            return None;
+        }
+        let channel = |index: usize| u8::from_str_radix(&hex[index..index + 2], 16).ok();
+        Some(Rgb::from_rgb(channel(0)?, channel(2)?, channel(4)?))
+    }
+
+    pub fn to_hex(self) -> String {
+        format!("#{:02X}{:02X}{:02X}", self.r, self.g, self.b)
+    }
+}
+
+// Stored as `#RRGGBB` strings in layouts, exports and saved state
+impl Serialize for Rgb {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        serializer.serialize_str(&self.to_hex())
+    }
+}
+
+impl<'de> Deserialize<'de> for Rgb {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        let hex: String = Deserialize::deserialize(deserializer)?;
+        Rgb::from_hex(&hex).ok_or_else(|| de::Error::custom(format!("invalid colour {}, expected #RRGGBB", hex)))
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
 pub struct DriverInfo {
     pub number: u32,
     pub name: &'static str,
     pub team: &'static str,
-    pub color: egui::Color32,
+    pub color: Rgb,
 }
 
 pub fn get_driver_info() -> Vec<DriverInfo> {
@@ -14,121 +81,121 @@ pub fn get_driver_info() -> Vec<DriverInfo> {
             number: 1,
             name: "Max Verstappen",
             team: "Red Bull",
-            color: egui::Color32::from_rgb(30, 65, 255),
+            color: Rgb::from_rgb(30, 65, 255),
         },
         DriverInfo {
             number: 2,
             name: "Logan Sargeant",
             team: "Williams",
-            color: egui::Color32::from_rgb(0, 82, 255),
+            color: Rgb::from_rgb(0, 82, 255),
         },
         DriverInfo {
             number: 4,
             name: "Lando Norris",
             team: "McLaren",
-            color: egui::Color32::from_rgb(255, 135, 0),
+            color: Rgb::from_rgb(255, 135, 0),
         },
         DriverInfo {
             number: 10,
             name: "Pierre Gasly",
             team: "Alpine",
-            color: egui::Color32::from_rgb(2, 144, 240),
+            color: Rgb::from_rgb(2, 144, 240),
         },
         DriverInfo {
             number: 11,
             name: "Sergio Perez",
             team: "Red Bull",
-            color: egui::Color32::from_rgb(30, 65, 255),
+            color: Rgb::from_rgb(30, 65, 255),
         },
         DriverInfo {
             number: 14,
             name: "Fernando Alonso",
             team: "Aston Martin",
-            color: egui::Color32::from_rgb(0, 110, 120),
+            color: Rgb::from_rgb(0, 110, 120),
         },
         DriverInfo {
             number: 16,
             name: "Charles Leclerc",
             team: "Ferrari",
-            color: egui::Color32::from_rgb(220, 0, 0),
+            color: Rgb::from_rgb(220, 0, 0),
         },
         DriverInfo {
             number: 18,
             name: "Lance Stroll",
             team: "Aston Martin",
-            color: egui::Color32::from_rgb(0, 110, 120),
+            color: Rgb::from_rgb(0, 110, 120),
         },
         DriverInfo {
             number: 20,
             name: "Kevin Magnussen",
             team: "Haas",
-            color: egui::Color32::from_rgb(160, 207, 205),
+            color: Rgb::from_rgb(160, 207, 205),
         },
         DriverInfo {
             number: 22,
             name: "Yuki Tsunoda",
             team: "AlphaTauri",
-            color: egui::Color32::from_rgb(60, 130, 200),
+            color: Rgb::from_rgb(60, 130, 200),
         },
         DriverInfo {
             number: 23,
             name: "Alex Albon",
             team: "Williams",
-            color: egui::Color32::from_rgb(0, 82, 255),
+            color: Rgb::from_rgb(0, 82, 255),
         },
         DriverInfo {
             number: 24,
             name: "Zhou Guanyu",
             team: "Stake F1",
-            color: egui::Color32::from_rgb(165, 160, 155),
+            color: Rgb::from_rgb(165, 160, 155),
         },
         DriverInfo {
             number: 27,
             name: "Nico Hulkenberg",
             team: "Haas",
-            color: egui::Color32::from_rgb(160, 207, 205),
+            color: Rgb::from_rgb(160, 207, 205),
         },
         DriverInfo {
             number: 31,
             name: "Esteban Ocon",
             team: "Alpine",
-            color: egui::Color32::from_rgb(2, 144, 240),
+            color: Rgb::from_rgb(2, 144, 240),
         },
         DriverInfo {
             number: 40,
             name: "Liam Lawson",
             team: "AlphaTauri",
-            color: egui::Color32::from_rgb(60, 130, 200),
+            color: Rgb::from_rgb(60, 130, 200),
         },
         DriverInfo {
             number: 44,
             name: "Lewis Hamilton",
             team: "Mercedes",
-            color: egui::Color32::from_rgb(0, 210, 190),
+            color: Rgb::from_rgb(0, 210, 190),
         },
         DriverInfo {
             number: 55,
             name: "Carlos Sainz",
             team: "Ferrari",
-            color: egui::Color32::from_rgb(220, 0, 0),
+            color: Rgb::from_rgb(220, 0, 0),
         },
         DriverInfo {
             number: 63,
             name: "George Russell",
             team: "Mercedes",
-            color: egui::Color32::from_rgb(0, 210, 190),
+            color: Rgb::from_rgb(0, 210, 190),
         },
         DriverInfo {
             number: 77,
             name: "Valtteri Bottas",
             team: "Stake F1",
-            color: egui::Color32::from_rgb(165, 160, 155),
+            color: Rgb::from_rgb(165, 160, 155),
         },
         DriverInfo {
             number: 81,
             name: "Oscar Piastri",
             team: "McLaren",
-            color: egui::Color32::from_rgb(255, 135, 0),
+            color: Rgb::from_rgb(255, 135, 0),
         },
     ]
 }
@@ -0,0 +1,348 @@
+use std::error::Error as StdError;
+use std::fmt::Write as _;
+use std::fs;
+use std::path::Path;
+
+use crate::delta;
+use crate::driver_info::DriverInfo;
+use crate::frames::{DriverData, VisualizationData};
+
+const VALUES_PER_LINE: usize = 12;
+// Firmware seldom uses every table
+const ALLOW_UNUSED: &str = "#[allow(dead_code)]\n";
+// How the firmware turns the tables into LEDs, written into both sources
+const PLAYING_FRAMES: [&str; 8] = [
+    "Playing frame i: with a keyframe interval of 0 every frame is stored in",
+    "full, so start from no cars. Otherwise frame i is a keyframe when",
+    "i % interval == 0 and holds every car, so start from no cars; any other",
+    "frame only holds the cars that changed since frame i - 1, so start from",
+    "the cars of frame i - 1. Then move each entry's car to its LED; LED 0",
+    "takes the car off the board (it retired or left the LEDs). Cars not in",
+    "the frame stay where they are. To seek, play from the last keyframe at",
+    "or before the frame.",
+];
+
+// Generates race data sources for the board firmware. Frames are flattened
+// into one array of (driver, LED) pairs indexed by per-frame offsets, which
+// needs no allocation on the device.
+pub struct FirmwareExport {
+    pub led_count: usize,
+    pub keyframe_interval: Option<usize>, // Delta compress frames when set
+    pub driver_info: Vec<DriverInfo>, // Colours written to the driver colour table
+}
+
+struct FlatFrames {
+    offsets: Vec<usize>, // FRAME_COUNT + 1 entries into `entries`
+    entries: Vec<(u8, usize)>,
+    dropped: usize, // Entries on LEDs beyond `led_count`
+}
+
+impl FirmwareExport {
+    pub fn write(&self, data: &VisualizationData, dir: &Path) -> Result<(), Box<dyn StdError>> {
+        for driver in &self.driver_info {
+            check_driver_number(driver.number)?;
+        }
+        let flat = self.flatten(data)?;
+        fs::create_dir_all(dir)?;
+        fs::write(dir.join("race_data.rs"), self.rust_module(data, &flat))?;
+        fs::write(dir.join("race_data.h"), self.c_header(data, &flat))?;
+
+        if flat.dropped > 0 {
+            eprintln!("Dropped {} entries on LEDs above {}", flat.dropped, self.led_count);
+        }
+        Ok(())
+    }
+
+    fn led_type(&self) -> (&'static str, &'static str) {
+        if self.led_count <= u8::MAX as usize {
+            ("u8", "uint8_t")
+        } else {
+            ("u16", "uint16_t")
+        }
+    }
+
+    fn flatten(&self, data: &VisualizationData) -> Result<FlatFrames, Box<dyn StdError>> {
+        // (driver, LED) entries per frame, `None` for a car that left
+        let entry = |driver: &DriverData| (driver.driver_number, Some(driver.led_num));
+        let frames: Vec<Vec<(u32, Option<usize>)>> = match self.keyframe_interval {
+            Some(interval) => delta::encode(data.frames.iter(), interval)
+                .map(|frame| {
+                    let left = frame.removed.iter().map(|&driver_number| (driver_number, None));
+                    frame.drivers.iter().map(entry).chain(left).collect()
+                })
+                .collect(),
+            None => data.frames.iter().map(|frame| frame.drivers.iter().map(entry).collect()).collect(),
+        };
+
+        let mut flat = FlatFrames {
+            offsets: vec![0],
+            entries: Vec::new(),
+            dropped: 0,
+        };
+        for entries in frames {
+            for (driver_number, led) in entries {
+                let driver_number = check_driver_number(driver_number)?;
+                let led = match led {
+                    Some(led) if led == 0 || led > self.led_count => {
+                        flat.dropped += 1;
+                        // A full frame just leaves the car out, a delta frame has to take it off
+                        if self.keyframe_interval.is_none() {
+                            continue;
+                        }
+                        0
+                    }
+                    Some(led) => led,
+                    None => 0,
+                };
+                flat.entries.push((driver_number, led));
+            }
+            flat.offsets.push(flat.entries.len());
+        }
+        Ok(flat)
+    }
+
+    fn rust_module(&self, data: &VisualizationData, flat: &FlatFrames) -> String {
+        let (led_type, _) = self.led_type();
+        let mut out = String::new();
+
+        out.push_str("// Generated by f1-led-circuit-master-simulation. Do not edit.\n");
+        out.push_str("// Only uses `core`, so it can be included in `no_std` firmware.\n");
+        if let Some(metadata) = &data.metadata {
+            let _ = writeln!(out, "// Session {} ({}, {})", metadata.session_key, metadata.meeting_name, metadata.circuit);
+        }
+        // Allowed per item, an inner attribute would break `include!`
+        out.push('\n');
+
+        let _ = writeln!(out, "{}pub const LED_COUNT: usize = {};", ALLOW_UNUSED, self.led_count);
+        let _ = writeln!(out, "{}pub const UPDATE_RATE_MS: u32 = {};", ALLOW_UNUSED, data.update_rate_ms);
+        let _ = writeln!(out, "{}pub const FRAME_COUNT: usize = {};", ALLOW_UNUSED, flat.offsets.len() - 1);
+        // Zero when frames are stored in full
+        let _ = writeln!(out, "{}pub const KEYFRAME_INTERVAL: usize = {};", ALLOW_UNUSED, self.keyframe_interval.unwrap_or(0));
+        out.push('\n');
+
+        out.push_str(ALLOW_UNUSED);
+        out.push_str("#[derive(Clone, Copy)]\n");
+        out.push_str("pub struct DriverLed {\n");
+        out.push_str("    pub driver_number: u8,\n");
+        let _ = writeln!(out, "    pub led: {}, // 1-based, 0 takes the car off", led_type);
+        out.push_str("}\n\n");
+
+        out.push_str("// Entries of frame `i` are DRIVER_LEDS[FRAME_OFFSETS[i]..FRAME_OFFSETS[i + 1]]\n");
+        for line in PLAYING_FRAMES {
+            let _ = writeln!(out, "// {}", line);
+        }
+        let _ = writeln!(out, "{}pub static FRAME_OFFSETS: [u32; {}] = [", ALLOW_UNUSED, flat.offsets.len());
+        write_values(&mut out, flat.offsets.iter().map(|offset| offset.to_string()), "    ");
+        out.push_str("];\n\n");
+
+        let _ = writeln!(out, "{}pub static DRIVER_LEDS: [DriverLed; {}] = [", ALLOW_UNUSED, flat.entries.len());
+        for (driver_number, led) in &flat.entries {
+            let _ = writeln!(out, "    DriverLed {{ driver_number: {}, led: {} }},", driver_number, led);
+        }
+        out.push_str("];\n\n");
+
+        out.push_str(ALLOW_UNUSED);
+        out.push_str("#[derive(Clone, Copy)]\n");
+        out.push_str("pub struct DriverColor {\n");
+        out.push_str("    pub driver_number: u8,\n");
+        out.push_str("    pub rgb: [u8; 3],\n");
+        out.push_str("}\n\n");
+
+        let _ = writeln!(out, "{}pub static DRIVER_COLORS: [DriverColor; {}] = [", ALLOW_UNUSED, self.driver_info.len());
+        for driver in &self.driver_info {
+            let _ = writeln!(
+                out,
+                "    DriverColor {{ driver_number: {}, rgb: [{}, {}, {}] }}, // {}",
+                driver.number, driver.color.r, driver.color.g, driver.color.b, driver.name
+            );
+        }
+        out.push_str("];\n");
+        out
+    }
+
+    fn c_header(&self, data: &VisualizationData, flat: &FlatFrames) -> String {
+        let (_, led_type) = self.led_type();
+        let mut out = String::new();
+
+        out.push_str("/* Generated by f1-led-circuit-master-simulation. Do not edit. */\n");
+        if let Some(metadata) = &data.metadata {
+            let _ = writeln!(out, "/* Session {} ({}, {}) */", metadata.session_key, metadata.meeting_name, metadata.circuit);
+        }
+        out.push_str("#ifndef F1_RACE_DATA_H\n#define F1_RACE_DATA_H\n\n#include <stdint.h>\n\n");
+
+        let _ = writeln!(out, "#define F1_LED_COUNT {}", self.led_count);
+        let _ = writeln!(out, "#define F1_UPDATE_RATE_MS {}", data.update_rate_ms);
+        let _ = writeln!(out, "#define F1_FRAME_COUNT {}", flat.offsets.len() - 1);
+        let _ = writeln!(out, "#define F1_KEYFRAME_INTERVAL {} /* 0 when frames are stored in full */", self.keyframe_interval.unwrap_or(0));
+        out.push('\n');
+
+        let _ = writeln!(out, "typedef struct {{\n    uint8_t driver_number;\n    {} led; /* 1-based, 0 takes the car off */\n}} f1_driver_led_t;\n", led_type);
+
+        out.push_str("/* Entries of frame i are f1_driver_leds[f1_frame_offsets[i]] up to f1_frame_offsets[i + 1].\n");
+        for line in PLAYING_FRAMES {
+            let _ = writeln!(out, " * {}", line);
+        }
+        out.push_str(" */\n");
+        let _ = writeln!(out, "static const uint32_t f1_frame_offsets[{}] = {{", flat.offsets.len());
+        write_values(&mut out, flat.offsets.iter().map(|offset| offset.to_string()), "    ");
+        out.push_str("};\n\n");
+
+        let _ = writeln!(out, "static const f1_driver_led_t f1_driver_leds[{}] = {{", flat.entries.len().max(1));
+        let mut entries: Vec<String> = flat
+            .entries
+            .iter()
+            .map(|(driver_number, led)| format!("{{{}, {}}}", driver_number, led))
+            .collect();
+        if entries.is_empty() {
+            entries.push("{0, 0}".to_string()); // C has no empty arrays
+        }
+        write_values(&mut out, entries.into_iter(), "    ");
+        out.push_str("};\n\n");
+
+        out.push_str("typedef struct {\n    uint8_t driver_number;\n    uint8_t rgb[3];\n} f1_driver_color_t;\n\n");
+        let _ = writeln!(out, "#define F1_DRIVER_COUNT {}", self.driver_info.len());
+        let _ = writeln!(out, "static const f1_driver_color_t f1_driver_colors[{}] = {{", self.driver_info.len().max(1));
+        for driver in &self.driver_info {
+            let _ = writeln!(
+                out,
+                "    {{{}, {{{}, {}, {}}}}}, /* {} */",
+                driver.number, driver.color.r, driver.color.g, driver.color.b, driver.name
+            );
+        }
+        if self.driver_info.is_empty() {
+            out.push_str("    {0, {0, 0, 0}},\n");
+        }
+        out.push_str("};\n\n#endif /* F1_RACE_DATA_H */\n");
+        out
+    }
+}
+
+// The generated tables store driver numbers as `u8`
+fn check_driver_number(driver_number: u32) -> Result<u8, Box<dyn StdError>> {
+    u8::try_from(driver_number)
+        .map_err(|_| format!("Driver number {} does not fit the firmware's 8-bit driver numbers", driver_number).into())
+}
+
+fn write_values(out: &mut String, values: impl Iterator<Item = String>, indent: &str) {
+    let values: Vec<String> = values.collect();
+    for line in values.chunks(VALUES_PER_LINE) {
+        let _ = writeln!(out, "{}{},", indent, line.join(", "));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::driver_info::Rgb;
+    use crate::frame_store::FrameStore;
+    use crate::frames::{FrameEncoding, UpdateFrame};
+
+    fn frame(drivers: &[(u32, usize)]) -> UpdateFrame {
+        UpdateFrame {
+            drivers: drivers
+                .iter()
+                .map(|&(driver_number, led_num)| DriverData { driver_number, led_num, pit_duration: None })
+                .collect(),
+            session_time: 0.0,
+            date: None,
+            positions: Vec::new(),
+        }
+    }
+
+    fn data() -> VisualizationData {
+        VisualizationData {
+            update_rate_ms: 250,
+            frames: FrameStore::from_frames([frame(&[(1, 10), (44, 20)]), frame(&[(1, 11), (44, 97)])]).unwrap(),
+            metadata: None,
+            encoding: FrameEncoding::Full,
+        }
+    }
+
+    fn export() -> FirmwareExport {
+        FirmwareExport {
+            led_count: 96,
+            keyframe_interval: None,
+            driver_info: vec![DriverInfo { number: 44, name: "Lewis Hamilton", team: "Mercedes", color: Rgb::from_rgb(0, 210, 190) }],
+        }
+    }
+
+    #[test]
+    fn frames_are_flattened_with_offsets() {
+        let flat = export().flatten(&data()).unwrap();
+        assert_eq!(flat.offsets, [0, 2, 3]);
+        assert_eq!(flat.entries, [(1, 10), (44, 20), (1, 11)]);
+        assert_eq!(flat.dropped, 1); // LED 97 of a 96 LED board
+    }
+
+    #[test]
+    fn generated_sources_match() {
+        let export = export();
+        let data = data();
+        let flat = export.flatten(&data).unwrap();
+
+        let rust = export.rust_module(&data, &flat);
+        assert!(rust.contains("pub const LED_COUNT: usize = 96;"));
+        // Fit for `include!`, which takes no inner attributes
+        assert!(!rust.contains("#!["));
+        for (line, next) in rust.lines().zip(rust.lines().skip(1)) {
+            if next.starts_with("pub ") {
+                assert!(line == "#[allow(dead_code)]" || line == "#[derive(Clone, Copy)]", "{} is not allowed unused", next);
+            }
+        }
+        assert!(rust.contains("pub const UPDATE_RATE_MS: u32 = 250;"));
+        assert!(rust.contains("pub const FRAME_COUNT: usize = 2;"));
+        assert!(rust.contains("pub static FRAME_OFFSETS: [u32; 3] = [\n    0, 2, 3,\n];"));
+        assert!(rust.contains("DriverLed { driver_number: 44, led: 20 },"));
+        assert!(rust.contains("DriverColor { driver_number: 44, rgb: [0, 210, 190] }, // Lewis Hamilton"));
+
+        let header = export.c_header(&data, &flat);
+        assert!(header.contains("#define F1_FRAME_COUNT 2"));
+        assert!(header.contains("static const f1_driver_led_t f1_driver_leds[3] = {\n    {1, 10}, {44, 20}, {1, 11},\n};"));
+        assert!(header.contains("    uint8_t led; /* 1-based, 0 takes the car off */"));
+        assert!(header.contains(" * the cars of frame i - 1."));
+    }
+
+    #[test]
+    fn driver_numbers_above_255_are_an_error() {
+        let mut wide = data();
+        wide.frames.push(&frame(&[(256, 1)])).unwrap();
+        assert!(export().flatten(&wide).is_err());
+
+        let mut export = export();
+        export.driver_info[0].number = 300;
+        let dir = std::env::temp_dir().join(format!("firmware_export_{}", std::process::id()));
+        assert!(export.write(&data(), &dir).is_err());
+        assert!(!dir.exists());
+    }
+
+    // The session `tests/firmware.rs` plays from the generated module: car 44
+    // runs off the board in frame 1 and retires in frame 3
+    fn compressed() -> (FirmwareExport, VisualizationData) {
+        let export = FirmwareExport { keyframe_interval: Some(2), ..export() };
+        let frames = [
+            frame(&[(1, 10), (44, 20)]),
+            frame(&[(1, 11), (44, 97)]),
+            frame(&[(1, 12), (44, 21)]),
+            frame(&[(1, 13)]),
+        ];
+        let data = VisualizationData { frames: FrameStore::from_frames(frames).unwrap(), ..data() };
+        (export, data)
+    }
+
+    #[test]
+    fn delta_frames_take_cars_off() {
+        let (export, data) = compressed();
+        let flat = export.flatten(&data).unwrap();
+        assert_eq!(flat.offsets, [0, 2, 4, 6, 8]);
+        assert_eq!(flat.entries, [(1, 10), (44, 20), (1, 11), (44, 0), (1, 12), (44, 21), (1, 13), (44, 0)]);
+        assert_eq!(flat.dropped, 1);
+    }
+
+    #[test]
+    fn the_compiled_fixture_is_up_to_date() {
+        let (export, data) = compressed();
+        let rust = export.rust_module(&data, &export.flatten(&data).unwrap());
+        // Regenerate with the output of this test if the format changes
+        assert_eq!(rust, include_str!("../tests/firmware/race_data.rs"), "{}", rust);
+    }
+}
@@ -0,0 +1,467 @@
+use chrono::{DateTime, Utc};
+use serde::de::{self, Deserializer};
+use serde::ser::SerializeStruct;
+use serde::{Deserialize, Serialize, Serializer};
+use std::error::Error as StdError;
+use std::path::Path;
+
+use crate::delta;
+use crate::frame_store::FrameStore;
+use crate::layout::TrackLayout;
+use crate::led_coords::LedCoordinate;
+use crate::pit::{PitStop, PitStopIndex};
+use crate::samples::LocationSamples;
+use crate::session::SessionMetadata;
+use crate::timestamp;
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct LocationData {
+    pub x: f64,
+    pub y: f64,
+    #[serde(deserialize_with = "deserialize_datetime")]
+    pub date: DateTime<Utc>,
+    pub driver_number: u32,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct DriverData {
+    pub driver_number: u32,
+    pub led_num: usize,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub pit_duration: Option<f64>, // Set while the car is in the pit lane
+}
+
+// Where a car actually was, before snapping to an LED
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct RawPosition {
+    pub driver_number: u32,
+    pub x: f64,
+    pub y: f64,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct UpdateFrame {
+    #[serde(deserialize_with = "deserialize_drivers")]
+    pub drivers: Vec<DriverData>, // One entry per car, any grid size
+    #[serde(default)]
+    pub session_time: f64, // Seconds since the first sample of the session
+    #[serde(default)]
+    pub date: Option<DateTime<Utc>>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub positions: Vec<RawPosition>, // Samples behind `drivers`, for the position overlay
+}
+
+// Version 1 files only have `update_rate_ms` and `frames`, version 2 adds
+// the metadata header and version 3 the optional delta encoding
+pub const FORMAT_VERSION: u32 = 3;
+
+// How frames are written to files; in memory they are always full frames
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub enum FrameEncoding {
+    #[default]
+    Full,
+    Delta { keyframe_interval: usize },
+}
+
+#[derive(Debug, Clone)]
+pub struct VisualizationData {
+    pub update_rate_ms: u64,
+    pub frames: FrameStore,
+    pub metadata: Option<SessionMetadata>,
+    pub encoding: FrameEncoding,
+}
+
+impl VisualizationData {
+    pub fn load(path: &Path) -> Result<VisualizationData, Box<dyn StdError>> {
+        let file = std::fs::File::open(path)?;
+        Ok(serde_json::from_reader(std::io::BufReader::new(file))?)
+    }
+
+    pub fn save(&self, path: &Path) -> Result<(), Box<dyn StdError>> {
+        let file = std::fs::File::create(path)?;
+        serde_json::to_writer(std::io::BufWriter::new(file), self)?;
+        Ok(())
+    }
+}
+
+// Implement custom Serialize and Deserialize for VisualizationData
+impl Serialize for VisualizationData {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        match self.encoding {
+            FrameEncoding::Full => {
+                let mut state = serializer.serialize_struct("VisualizationData", 4)?;
+                state.serialize_field("format_version", &FORMAT_VERSION)?;
+                state.serialize_field("metadata", &self.metadata)?;
+                state.serialize_field("update_rate_ms", &self.update_rate_ms)?;
+                state.serialize_field("frames", &self.frames)?;
+                state.end()
+            }
+            FrameEncoding::Delta { keyframe_interval } => {
+                let mut state = serializer.serialize_struct("VisualizationData", 6)?;
+                state.serialize_field("format_version", &FORMAT_VERSION)?;
+                state.serialize_field("metadata", &self.metadata)?;
+                state.serialize_field("update_rate_ms", &self.update_rate_ms)?;
+                state.serialize_field("encoding", "delta")?;
+                state.serialize_field("keyframe_interval", &keyframe_interval)?;
+                state.serialize_field("frames", &DeltaFrames(&self.frames, keyframe_interval))?;
+                state.end()
+            }
+        }
+    }
+}
+
+impl<'de> Deserialize<'de> for VisualizationData {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        #[derive(Deserialize)]
+        struct VisualizationDataHelper {
+            #[serde(default = "first_format_version")]
+            format_version: u32,
+            #[serde(default)]
+            metadata: Option<SessionMetadata>,
+            update_rate_ms: u64,
+            #[serde(default)]
+            encoding: Option<String>,
+            #[serde(default)]
+            keyframe_interval: Option<usize>,
+            frames: StoredFrames,
+        }
+
+        fn first_format_version() -> u32 {
+            1
+        }
+
+        let helper = VisualizationDataHelper::deserialize(deserializer)?;
+        if helper.format_version > FORMAT_VERSION {
+            return Err(de::Error::custom(format!(
+                "unsupported format version {} (newest supported is {})",
+                helper.format_version, FORMAT_VERSION
+            )));
+        }
+
+        let StoredFrames { frames, keyframes, removed } = helper.frames;
+        let (frames, encoding) = match helper.encoding.as_deref() {
+            None | Some("full") => (frames, FrameEncoding::Full),
+            Some("delta") => (
+                FrameStore::from_frames(delta::decode(frames.iter().zip(keyframes).zip(removed).map(
+                    |((frame, keyframe), removed)| delta::DeltaFrame {
+                        keyframe,
+                        drivers: frame.drivers,
+                        removed,
+                        session_time: frame.session_time,
+                        date: frame.date,
+                        positions: frame.positions,
+                    },
+                )))
+                .map_err(de::Error::custom)?,
+                FrameEncoding::Delta {
+                    keyframe_interval: helper
+                        .keyframe_interval
+                        .unwrap_or(delta::DEFAULT_KEYFRAME_INTERVAL),
+                },
+            ),
+            Some(other) => {
+                return Err(de::Error::custom(format!("unknown frame encoding {}", other)));
+            }
+        };
+
+        Ok(VisualizationData {
+            update_rate_ms: helper.update_rate_ms,
+            frames,
+            metadata: helper.metadata,
+            encoding,
+        })
+    }
+}
+
+// Delta encoded frames, encoded while they are written
+struct DeltaFrames<'a>(&'a FrameStore, usize);
+
+impl Serialize for DeltaFrames<'_> {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        serializer.collect_seq(delta::encode(self.0.iter(), self.1))
+    }
+}
+
+// Frames as read from a file, before delta decoding. They go straight into a
+// `FrameStore` as they are parsed, so a whole session is never held as
+// `UpdateFrame`s.
+struct StoredFrames {
+    frames: FrameStore,
+    keyframes: Vec<bool>,
+    removed: Vec<Vec<u32>>, // Cars that left, per frame; only delta files have any
+}
+
+impl<'de> Deserialize<'de> for StoredFrames {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        struct FramesVisitor;
+
+        impl<'de> de::Visitor<'de> for FramesVisitor {
+            type Value = StoredFrames;
+
+            fn expecting(&self, formatter: &mut std::fmt::Formatter) -> std::fmt::Result {
+                formatter.write_str("a list of frames")
+            }
+
+            fn visit_seq<A>(self, mut seq: A) -> Result<StoredFrames, A::Error>
+            where
+                A: de::SeqAccess<'de>,
+            {
+                let mut stored = StoredFrames {
+                    frames: FrameStore::new(),
+                    keyframes: Vec::new(),
+                    removed: Vec::new(),
+                };
+                while let Some(mut frame) = seq.next_element::<delta::DeltaFrame>()? {
+                    stored.keyframes.push(frame.keyframe);
+                    stored.removed.push(std::mem::take(&mut frame.removed));
+                    stored.frames.push(&UpdateFrame::from(frame)).map_err(de::Error::custom)?;
+                }
+                stored.frames.shrink_to_fit();
+                Ok(stored)
+            }
+        }
+
+        deserializer.deserialize_seq(FramesVisitor)
+    }
+}
+
+// Older files store a fixed array of 20 slots with `null` for empty ones
+pub(crate) fn deserialize_drivers<'de, D>(deserializer: D) -> Result<Vec<DriverData>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    let slots: Vec<Option<DriverData>> = Deserialize::deserialize(deserializer)?;
+    Ok(slots.into_iter().flatten().collect())
+}
+
+pub(crate) fn deserialize_datetime<'de, D>(deserializer: D) -> Result<DateTime<Utc>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    let s: String = Deserialize::deserialize(deserializer)?;
+    timestamp::parse_utc(&s).map_err(de::Error::custom)
+}
+
+// Snaps location samples to the nearest LED of a layout
+pub struct LedMapper<'a> {
+    pit_stops: PitStopIndex<'a>,
+    pit_coords: Vec<&'a LedCoordinate>,
+    track_coords: Vec<&'a LedCoordinate>,
+}
+
+impl<'a> LedMapper<'a> {
+    pub fn new(layout: &'a TrackLayout, pit_stops: &'a [PitStop]) -> LedMapper<'a> {
+        // Pit lane LEDs only light up for cars that are actually in the pit lane
+        let (pit_coords, track_coords) = layout
+            .leds
+            .iter()
+            .partition(|coord| layout.is_pit_lane(coord.led_number));
+
+        LedMapper {
+            pit_stops: PitStopIndex::new(pit_stops),
+            pit_coords,
+            track_coords,
+        }
+    }
+
+    // Driver data for the sample and its distance to the chosen LED, `None`
+    // if the layout has no LEDs. A layout that is all pit lane maps every
+    // car onto it.
+    pub fn map(&self, data: &LocationData) -> Option<(DriverData, f64)> {
+        let pit_stop = self.pit_stops.stop_at(data.driver_number, data.date);
+        let coordinates = if (pit_stop.is_some() || self.track_coords.is_empty()) && !self.pit_coords.is_empty() {
+            &self.pit_coords
+        } else {
+            &self.track_coords
+        };
+
+        let (nearest_coord, distance) = coordinates
+            .iter()
+            .map(|coord| {
+                let distance =
+                    ((data.x - coord.x_led).powi(2) + (data.y - coord.y_led).powi(2)).sqrt();
+                (coord, distance)
+            })
+            .min_by(|(_, dist_a), (_, dist_b)| {
+                dist_a
+                    .partial_cmp(dist_b)
+                    .unwrap_or(std::cmp::Ordering::Equal)
+            })?;
+
+        let driver_data = DriverData {
+            driver_number: data.driver_number,
+            led_num: nearest_coord.led_number,
+            pit_duration: pit_stop.map(PitStop::duration),
+        };
+        Some((driver_data, distance))
+    }
+}
+
+// Maps time-ordered samples to frames as they are read, so frames can be
+// generated from any source without holding them all. A frame holds one
+// sample per car on the grid.
+pub struct FrameStream<'a, I> {
+    samples: I,
+    mapper: LedMapper<'a>,
+    grid_size: usize,
+    session_start: Option<DateTime<Utc>>,
+}
+
+impl<'a, I: Iterator<Item = LocationData>> FrameStream<'a, I> {
+    pub fn new(samples: I, grid_size: usize, layout: &'a TrackLayout, pit_stops: &'a [PitStop]) -> FrameStream<'a, I> {
+        FrameStream {
+            samples,
+            mapper: LedMapper::new(layout, pit_stops),
+            grid_size,
+            session_start: None,
+        }
+    }
+}
+
+impl<I: Iterator<Item = LocationData>> Iterator for FrameStream<'_, I> {
+    type Item = UpdateFrame;
+
+    fn next(&mut self) -> Option<UpdateFrame> {
+        let mut frame = UpdateFrame::default();
+
+        for data in self.samples.by_ref() {
+            // Nothing to show a car on without LEDs
+            let Some((driver_data, _distance)) = self.mapper.map(&data) else {
+                continue;
+            };
+            let session_start = *self.session_start.get_or_insert(data.date);
+
+            // A frame is stamped with the time of its first sample
+            if frame.drivers.is_empty() {
+                frame.date = Some(data.date);
+                frame.session_time = (data.date - session_start).num_milliseconds() as f64 / 1000.0;
+            }
+
+            // Insert the driver data into the frame, keeping the raw sample next to it
+            frame.drivers.push(driver_data);
+            frame.positions.push(RawPosition {
+                driver_number: data.driver_number,
+                x: data.x,
+                y: data.y,
+            });
+
+            // Once the frame is full, hand it out and start a new one
+            if frame.drivers.len() >= self.grid_size {
+                return Some(frame);
+            }
+        }
+
+        // The last frame if it has any data
+        (!frame.drivers.is_empty()).then_some(frame)
+    }
+}
+
+pub fn generate_update_frames(
+    samples: &LocationSamples,
+    layout: &TrackLayout,
+    pit_stops: &[PitStop],
+) -> Result<FrameStore, Box<dyn StdError>> {
+    FrameStore::from_frames(FrameStream::new(samples.iter(), samples.driver_numbers().len(), layout, pit_stops))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::layout::LedRange;
+
+    fn layout(pit_lane: Option<LedRange>) -> TrackLayout {
+        let leds = (1..=4)
+            .map(|led_number| LedCoordinate { x_led: led_number as f64 * 100.0, y_led: 0.0, led_number })
+            .collect();
+        TrackLayout { pit_lane, ..TrackLayout::from_coordinates("test", "Test", leds) }
+    }
+
+    fn sample(x: f64) -> LocationData {
+        LocationData {
+            x,
+            y: 10.0,
+            date: DateTime::from_timestamp(1_693_141_200, 0).unwrap(),
+            driver_number: 1,
+        }
+    }
+
+    #[test]
+    fn samples_map_to_the_nearest_led() {
+        let layout = layout(Some(LedRange::new(4, 4)));
+        let mapper = LedMapper::new(&layout, &[]);
+        let (driver, distance) = mapper.map(&sample(210.0)).unwrap();
+        assert_eq!(driver.led_num, 2);
+        assert!((distance - 200f64.sqrt()).abs() < 1e-9);
+        // Pit lane LEDs are only for cars in the pit lane
+        assert_eq!(mapper.map(&sample(420.0)).unwrap().0.led_num, 3);
+    }
+
+    #[test]
+    fn layouts_without_track_leds_do_not_panic() {
+        let pit_only = layout(Some(LedRange::new(1, 4)));
+        let (driver, _) = LedMapper::new(&pit_only, &[]).map(&sample(420.0)).unwrap();
+        assert_eq!(driver.led_num, 4);
+
+        let empty = TrackLayout::from_coordinates("empty", "Empty", Vec::new());
+        assert!(LedMapper::new(&empty, &[]).map(&sample(420.0)).is_none());
+        assert_eq!(FrameStream::new([sample(1.0), sample(2.0)].into_iter(), 1, &empty, &[]).count(), 0);
+    }
+
+    #[test]
+    fn both_encodings_play_the_same() {
+        let driver = |driver_number, led_num| DriverData { driver_number, led_num, pit_duration: None };
+        // Unordered, a car twice in a frame, a car missing for a frame and one retiring
+        let frames = [
+            vec![driver(44, 3), driver(1, 5), driver(44, 4)],
+            vec![driver(44, 4), driver(16, 2)],
+            vec![driver(1, 6), driver(16, 2), driver(44, 5)],
+            vec![driver(1, 7), driver(44, 6)],
+            vec![driver(1, 8), driver(44, 6)],
+        ];
+        let mut data = VisualizationData {
+            update_rate_ms: 100,
+            frames: FrameStore::from_frames(frames.iter().enumerate().map(|(i, drivers)| UpdateFrame {
+                drivers: drivers.clone(),
+                session_time: i as f64 / 10.0,
+                ..UpdateFrame::default()
+            }))
+            .unwrap(),
+            metadata: None,
+            encoding: FrameEncoding::Full,
+        };
+        let generated: Vec<UpdateFrame> = data.frames.iter().collect();
+        assert_eq!(generated[0].drivers, vec![driver(1, 5), driver(44, 4)]);
+        assert_eq!(generated[3].drivers, vec![driver(1, 7), driver(44, 6)]); // Car 16 is gone
+
+        let full: VisualizationData = serde_json::from_slice(&serde_json::to_vec(&data).unwrap()).unwrap();
+        assert_eq!(full.frames.iter().collect::<Vec<_>>(), generated);
+        for keyframe_interval in [1, 2, 50] {
+            data.encoding = FrameEncoding::Delta { keyframe_interval };
+            let delta: VisualizationData = serde_json::from_slice(&serde_json::to_vec(&data).unwrap()).unwrap();
+            assert_eq!(delta.frames.iter().collect::<Vec<_>>(), generated, "keyframe every {}", keyframe_interval);
+        }
+
+        // A file written raw, as before normalising, reads back the same
+        let raw = serde_json::json!({
+            "update_rate_ms": 100,
+            "frames": frames.iter().enumerate().map(|(i, drivers)| serde_json::json!({
+                "drivers": drivers,
+                "session_time": i as f64 / 10.0,
+            })).collect::<Vec<_>>(),
+        });
+        let old: VisualizationData = serde_json::from_value(raw).unwrap();
+        assert_eq!(old.frames.iter().collect::<Vec<_>>(), generated);
+    }
+}
@@ -0,0 +1,860 @@
+use eframe::{egui, App, Frame};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use std::sync::Arc;
+use web_time::Duration;
+
+use crate::colors::ColorScheme;
+use crate::driver_info::{DriverInfo, Rgb};
+use crate::frame_store::FrameStore;
+use crate::frames::{DriverData, RawPosition, UpdateFrame};
+use crate::hardware::{frame_colors, preview_color, ColorCorrection};
+use crate::layout::TrackLayout;
+use crate::led_coords::LedCoordinate;
+use crate::playback::{OutputStyle, Playback};
+use crate::sinks::OutputSinks;
+
+// Track view limits, in points
+const VIEW_MARGIN: f32 = 30.0;
+const MIN_LED_SIZE: f32 = 3.0;
+const MAX_LED_SIZE: f32 = 20.0; // At zoom 1, the size LEDs used to be drawn at
+const MIN_ZOOM: f32 = 0.25;
+const MAX_ZOOM: f32 = 20.0;
+
+// Window title, also the eframe app id that names the storage directory
+pub const APP_NAME: &str = "F1-LED-CIRCUIT SIMULATION";
+
+// Bump when a field changes meaning; added or removed fields only need
+// `#[serde(default)]`
+pub const UI_STATE_VERSION: u32 = 1;
+
+const TRACK_COLOR: egui::Color32 = egui::Color32::from_gray(70);
+const PIT_LANE_COLOR: egui::Color32 = egui::Color32::from_gray(50);
+
+const SECTOR_COLORS: [egui::Color32; 3] = [
+    egui::Color32::from_rgb(90, 30, 30),
+    egui::Color32::from_rgb(30, 40, 90),
+    egui::Color32::from_rgb(90, 80, 20),
+];
+
+// UI state restored between runs through eframe persistence
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct UiState {
+    pub version: u32,
+    pub speed: i32, // Playback speed multiplier
+    pub show_regions: bool, // Draw sector, DRS and corner underlay
+    pub show_outline: bool, // Draw the circuit, pit lane and start/finish line
+    pub show_positions: bool, // Draw raw car positions against their LEDs
+    pub preview_hardware: bool, // Show LEDs as the colour corrected hardware would
+    pub show_legend: bool,
+    pub hidden_drivers: BTreeSet<u32>, // Drivers left off the board
+    pub hide_on_outputs: bool, // Also leave hidden drivers off the hardware outputs
+    pub view: ViewState,
+    pub color_scheme: ColorScheme,
+    pub color_overrides: BTreeMap<u32, Rgb>, // Picked in the legend or from `--color-overrides`
+    pub data_source: Option<String>, // Exported data file or URL of the last session
+    pub layout_source: Option<String>, // Layout file or URL, `None` for the built-in one
+}
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ViewState {
+    pub zoom: f32,
+    pub pan: [f32; 2], // Screen offset in points
+}
+
+impl Default for UiState {
+    fn default() -> UiState {
+        UiState {
+            version: UI_STATE_VERSION,
+            speed: 1,
+            show_regions: true,
+            show_outline: true,
+            show_positions: false,
+            preview_hardware: false,
+            show_legend: true,
+            hidden_drivers: BTreeSet::new(),
+            hide_on_outputs: false,
+            view: ViewState::default(),
+            color_scheme: ColorScheme::default(),
+            color_overrides: BTreeMap::new(),
+            data_source: None,
+            layout_source: None,
+        }
+    }
+}
+
+impl Default for ViewState {
+    fn default() -> ViewState {
+        ViewState { zoom: 1.0, pan: [0.0, 0.0] }
+    }
+}
+
+impl UiState {
+    // Saved state, or the defaults when there is none or it can't be used
+    pub fn restore(storage: Option<&dyn eframe::Storage>) -> UiState {
+        storage
+            .and_then(|storage| eframe::get_value::<UiState>(storage, eframe::APP_KEY))
+            .filter(|state| state.version <= UI_STATE_VERSION) // Written by a newer build
+            .map(|state| UiState {
+                version: UI_STATE_VERSION,
+                speed: state.speed.clamp(1, 5),
+                view: ViewState {
+                    zoom: state.view.zoom.clamp(MIN_ZOOM, MAX_ZOOM),
+                    ..state.view
+                },
+                ..state
+            })
+            .unwrap_or_default()
+    }
+
+    // Reads the native app storage without starting eframe, for `--resume`
+    #[cfg(not(target_arch = "wasm32"))]
+    pub fn load_saved() -> Option<UiState> {
+        let path = eframe::storage_dir(APP_NAME)?.join("app.ron");
+        let contents = std::fs::read_to_string(path).ok()?;
+        let values: HashMap<String, String> = ron::from_str(&contents).ok()?;
+        let state: UiState = ron::from_str(values.get(eframe::APP_KEY)?).ok()?;
+        (state.version <= UI_STATE_VERSION).then_some(state)
+    }
+}
+
+pub struct PlotApp {
+    update_rate_ms: u64,
+    frames: Arc<FrameStore>, // Shared with the playback thread
+    layout: TrackLayout,
+    playback: Playback, // Clock and outputs, running without the window
+    waker_set: bool,    // Whether playback repaints the window yet
+    race_time: f64,     // Elapsed race time in seconds
+    base_driver_info: Vec<DriverInfo>, // As given, before the colour scheme
+    driver_info: Vec<DriverInfo>,
+    current_index: usize,
+    current: Option<UpdateFrame>, // Unpacked from `frames` for `current_index`
+    next: Option<UpdateFrame>,
+    led_states: HashMap<usize, egui::Color32>, // Tracks the current state of the LEDs
+    state: UiState,
+    highlighted: Vec<u32>, // Drivers hovered in the legend this frame
+    correction: ColorCorrection, // Applied to everything sent to the outputs
+    output_current_ma: f32, // Estimated board current for the frame on screen
+}
+
+impl PlotApp {
+    pub fn new(
+        update_rate_ms: u64,
+        frames: FrameStore,
+        layout: TrackLayout,
+        driver_info: Vec<DriverInfo>,
+    ) -> PlotApp {
+        let frames = Arc::new(frames);
+        let mut app = PlotApp {
+            update_rate_ms,
+            playback: Playback::new(update_rate_ms, frames.clone(), layout.clone()),
+            frames,
+            layout,
+            waker_set: false,
+            race_time: 0.0,
+            base_driver_info: driver_info.clone(),
+            driver_info,
+            current_index: 0,
+            current: None,
+            next: None,
+            led_states: HashMap::new(), // Initialize empty LED state tracking
+            state: UiState::default(),
+            highlighted: Vec::new(),
+            correction: ColorCorrection::default(),
+            output_current_ma: 0.0,
+        };
+        app.recolor();
+        app
+    }
+
+    // Applies state saved by a previous run, keeping the sources this run was started with
+    pub fn restore_state(&mut self, state: UiState) {
+        let sources = (self.state.data_source.take(), self.state.layout_source.take());
+        self.state = state;
+        (self.state.data_source, self.state.layout_source) = sources;
+        self.recolor();
+    }
+
+    // Colours chosen on the command line win over the saved ones
+    pub fn set_colors(&mut self, scheme: Option<ColorScheme>, overrides: BTreeMap<u32, Rgb>) {
+        if let Some(scheme) = scheme {
+            self.state.color_scheme = scheme;
+        }
+        self.state.color_overrides.extend(overrides);
+        self.recolor();
+    }
+
+    fn recolor(&mut self) {
+        self.driver_info = self
+            .state
+            .color_scheme
+            .apply(&self.base_driver_info, &self.state.color_overrides);
+    }
+
+    // Where the data and layout came from, remembered for the next run
+    pub fn set_sources(&mut self, data_source: Option<String>, layout_source: Option<String>) {
+        self.state.data_source = data_source;
+        self.state.layout_source = layout_source;
+    }
+
+    pub fn set_outputs(&mut self, outputs: OutputSinks) {
+        self.playback.set_outputs(outputs);
+    }
+
+    // The screen counts as a sink too: with a latency it is shown frames
+    // ahead of the clock, like the outputs
+    pub fn set_display_latency(&mut self, latency: Duration) {
+        self.playback.set_display_latency(latency);
+    }
+
+    pub fn set_color_correction(&mut self, correction: ColorCorrection) {
+        self.correction = correction;
+    }
+
+    fn reset(&mut self) {
+        self.playback.reset(); // Turns the boards off as well
+        self.led_states.clear(); // Reset LED states
+    }
+
+    // Catches up with the playback thread and hands it the current settings
+    fn update_race(&mut self) {
+        // The slider and `restore` keep the speed within 1 to 5
+        self.playback.set_speed(self.state.speed as f64).unwrap();
+        self.playback.set_style(self.output_style());
+        self.race_time = self.playback.race_time();
+        let index = self.playback.index();
+        if index != self.current_index {
+            self.set_index(index);
+        }
+        if self.playback.is_playing() {
+            self.update_led_states();
+        }
+    }
+
+    fn set_index(&mut self, index: usize) {
+        self.current_index = index;
+        self.current = index.checked_sub(1).and_then(|current| self.frames.frame(current));
+        self.next = self.frames.frame(index);
+    }
+
+    // Hidden drivers stay on the boards unless configured otherwise
+    fn output_style(&self) -> OutputStyle {
+        OutputStyle {
+            driver_info: self.driver_info.clone(),
+            hidden_drivers: if self.state.hide_on_outputs {
+                self.state.hidden_drivers.clone()
+            } else {
+                BTreeSet::new()
+            },
+            correction: self.correction,
+        }
+    }
+
+    // The frame without the drivers hidden in the legend
+    fn visible(&self, frame: &UpdateFrame) -> UpdateFrame {
+        UpdateFrame {
+            drivers: frame
+                .drivers
+                .iter()
+                .filter(|d| !self.state.hidden_drivers.contains(&d.driver_number))
+                .copied()
+                .collect(),
+            ..frame.clone()
+        }
+    }
+
+    fn update_led_states(&mut self) {
+        self.led_states.clear();
+
+        let Some(frame) = self.current_frame() else {
+            return;
+        };
+        let visible_colors = frame_colors(&self.visible(frame), &self.layout, &self.driver_info);
+        let shown = if self.state.preview_hardware {
+            self.correction.apply(&visible_colors).into_iter().map(preview_color).collect()
+        } else {
+            visible_colors
+        };
+        let output_colors = self.output_style().colors(frame, &self.layout);
+        self.output_current_ma = self.correction.estimated_current_ma(&output_colors);
+        for (index, &color) in shown.iter().enumerate() {
+            if color != Rgb::BLACK {
+                self.led_states.insert(index + 1, to_color32(color));
+            }
+        }
+    }
+
+    // Click toggles a driver, shift-click shows only that driver or, when it
+    // already is the only one, everyone again
+    fn toggle_driver(&mut self, driver_number: u32, solo: bool) {
+        let hidden = &mut self.state.hidden_drivers;
+        if !solo {
+            if !hidden.remove(&driver_number) {
+                hidden.insert(driver_number);
+            }
+            return;
+        }
+
+        let others: BTreeSet<u32> = self
+            .driver_info
+            .iter()
+            .map(|d| d.number)
+            .filter(|&number| number != driver_number)
+            .collect();
+        if *hidden == others {
+            hidden.clear();
+        } else {
+            *hidden = others;
+        }
+    }
+
+    // Shows the whole team unless all of it is visible already, then hides it
+    fn toggle_team(&mut self, team: &str) {
+        let members: Vec<u32> = self.driver_info.iter().filter(|d| d.team == team).map(|d| d.number).collect();
+        if members.iter().all(|number| !self.state.hidden_drivers.contains(number)) {
+            self.state.hidden_drivers.extend(members);
+        } else {
+            for number in members {
+                self.state.hidden_drivers.remove(&number);
+            }
+        }
+    }
+
+    fn current_frame(&self) -> Option<&UpdateFrame> {
+        self.current.as_ref()
+    }
+
+    fn driver_data(&self, driver_number: u32) -> Option<&DriverData> {
+        self.current_frame()?
+            .drivers
+            .iter()
+            .find(|d| d.driver_number == driver_number)
+    }
+
+    // Sector, corner and pit suffix for the legend, empty before the race starts
+    fn driver_location(&self, driver_number: u32) -> String {
+        let Some(driver) = self.driver_data(driver_number) else {
+            return String::new();
+        };
+        if let Some(pit_duration) = driver.pit_duration {
+            return format!(" - PIT {:.1}s", pit_duration);
+        }
+
+        let led_num = driver.led_num;
+        let mut location = match self.layout.sector_of(led_num) {
+            Some(sector) => format!(" - S{}", sector),
+            None => String::new(),
+        };
+        if let Some(corner) = self.layout.corner_of(led_num) {
+            location.push(' ');
+            location.push_str(&corner.name);
+        }
+        location
+    }
+
+    // Wheel or pinch zooms around the pointer, dragging pans and a double
+    // click fits the track to the panel again
+    fn handle_view_input(&mut self, ui: &egui::Ui, response: &egui::Response) {
+        let view = &mut self.state.view;
+        if response.double_clicked() {
+            *view = ViewState::default();
+            return;
+        }
+        if response.dragged() {
+            let delta = response.drag_delta();
+            view.pan[0] += delta.x;
+            view.pan[1] += delta.y;
+        }
+
+        let Some(pointer) = response.hover_pos() else {
+            return;
+        };
+        let (scroll, pinch) = ui.input(|i| (i.scroll_delta.y, i.zoom_delta()));
+        let factor = pinch * (scroll / 200.0).exp();
+        if factor != 1.0 {
+            let zoom = (view.zoom * factor).clamp(MIN_ZOOM, MAX_ZOOM);
+            // Keep the point under the pointer in place
+            let from_center = pointer - response.rect.center() - egui::vec2(view.pan[0], view.pan[1]);
+            let pan = pointer - response.rect.center() - from_center * (zoom / view.zoom);
+            view.pan = [pan.x, pan.y];
+            view.zoom = zoom;
+        }
+    }
+
+    // Grey track band with the pit lane and a start/finish line across the track
+    fn draw_track_outline(&self, painter: &egui::Painter, transform: &TrackTransform) {
+        let to_screen = |points: &[(f64, f64)]| -> Vec<egui::Pos2> {
+            points.iter().map(|&(x, y)| transform.to_screen_point(x, y)).collect()
+        };
+        let band = transform.led_size * 1.8;
+
+        let pit_lane = to_screen(&self.layout.pit_lane_path());
+        if pit_lane.len() >= 2 {
+            painter.add(egui::Shape::line(pit_lane, egui::Stroke::new(band * 0.6, PIT_LANE_COLOR)));
+        }
+
+        let outline = self.layout.track_outline();
+        if outline.len() >= 2 {
+            painter.add(egui::Shape::closed_line(to_screen(&outline), egui::Stroke::new(band, TRACK_COLOR)));
+        }
+
+        let Some(coord) = self.layout.start_finish_led.and_then(|led| self.layout.coordinate(led)) else {
+            return;
+        };
+        let center = transform.to_screen(coord);
+        // Across the direction of the nearest outline segment
+        let direction = nearest_segment(&outline, (coord.x_led, coord.y_led))
+            .map(|(a, b)| transform.to_screen_point(b.0, b.1) - transform.to_screen_point(a.0, a.1))
+            .filter(|direction| direction.length() > 0.0)
+            .map_or(egui::vec2(1.0, 0.0), |direction| direction.normalized());
+        let across = egui::vec2(-direction.y, direction.x) * band * 0.6;
+        painter.line_segment([center - across, center + across], egui::Stroke::new(3.0, egui::Color32::WHITE));
+    }
+
+    // Each visible car's raw position, interpolated towards the next frame, as
+    // a dot joined to the LED it was snapped to
+    fn draw_position_overlay(&self, painter: &egui::Painter, transform: &TrackTransform) {
+        let Some(frame) = self.current_frame() else {
+            return;
+        };
+        let next = self.next.as_ref();
+        let frame_duration = self.update_rate_ms as f64 / 1000.0;
+        let t = (self.playback.display_time() / frame_duration - (self.current_index - 1) as f64).clamp(0.0, 1.0);
+
+        for position in &frame.positions {
+            if self.state.hidden_drivers.contains(&position.driver_number) {
+                continue;
+            }
+            let (x, y) = match next.and_then(|next| raw_position(next, position.driver_number)) {
+                Some(to) => (position.x + (to.x - position.x) * t, position.y + (to.y - position.y) * t),
+                None => (position.x, position.y),
+            };
+            let dot = transform.to_screen_point(x, y);
+            let color = self
+                .driver_info
+                .iter()
+                .find(|d| d.number == position.driver_number)
+                .map_or(egui::Color32::WHITE, |d| to_color32(d.color));
+
+            let led = frame
+                .drivers
+                .iter()
+                .find(|d| d.driver_number == position.driver_number)
+                .and_then(|d| self.layout.coordinate(d.led_num));
+            if let Some(led) = led {
+                painter.line_segment([dot, transform.to_screen(led)], egui::Stroke::new(1.0, color));
+            }
+            painter.circle(dot, 4.0, color, egui::Stroke::new(1.0, egui::Color32::BLACK));
+            painter.text(
+                dot + egui::vec2(5.0, -5.0),
+                egui::Align2::LEFT_BOTTOM,
+                position.driver_number.to_string(),
+                egui::FontId::proportional(10.0),
+                egui::Color32::WHITE,
+            );
+        }
+    }
+
+    fn draw_region_underlay(&self, painter: &egui::Painter, transform: &TrackTransform) {
+        let size = transform.led_size;
+        for coord in &self.layout.leds {
+            let center = transform.to_screen(coord);
+
+            if let Some(sector) = self.layout.sector_of(coord.led_number) {
+                painter.rect_filled(
+                    egui::Rect::from_center_size(center, egui::vec2(size * 1.5, size * 1.5)),
+                    egui::Rounding::same(size * 0.2),
+                    SECTOR_COLORS[(sector - 1) % SECTOR_COLORS.len()],
+                );
+            }
+
+            if self.layout.is_drs_zone(coord.led_number) {
+                painter.rect_stroke(
+                    egui::Rect::from_center_size(center, egui::vec2(size * 1.3, size * 1.3)),
+                    egui::Rounding::same(size * 0.1),
+                    egui::Stroke::new(2.0, egui::Color32::GREEN),
+                );
+            }
+
+            if self.layout.is_pit_lane(coord.led_number) {
+                painter.rect_stroke(
+                    egui::Rect::from_center_size(center, egui::vec2(size * 1.3, size * 1.3)),
+                    egui::Rounding::same(size * 0.1),
+                    egui::Stroke::new(2.0, egui::Color32::YELLOW),
+                );
+            }
+        }
+
+        for corner in &self.layout.corners {
+            if let Some(coord) = self.layout.coordinate(corner.leds.first) {
+                painter.text(
+                    transform.to_screen(coord) + egui::vec2(size * 0.75, -size * 0.75),
+                    egui::Align2::LEFT_BOTTOM,
+                    &corner.name,
+                    egui::FontId::proportional(10.0),
+                    egui::Color32::GRAY,
+                );
+            }
+        }
+    }
+}
+
+impl App for PlotApp {
+    fn update(&mut self, ctx: &egui::Context, _frame: &mut Frame) {
+        if !self.waker_set {
+            let ctx = ctx.clone();
+            self.playback.set_waker(move || ctx.request_repaint());
+            self.waker_set = true;
+        }
+        self.update_race();
+
+        egui::TopBottomPanel::top("top_panel").show(ctx, |ui| {
+            ui.horizontal(|ui| {
+                ui.separator();
+                ui.label(format!(
+                    "Race Time: {:02}:{:02}:{:05.2}",
+                    (self.race_time / 3600.0).floor() as u32, // hours
+                    ((self.race_time % 3600.0) / 60.0).floor() as u32, // minutes
+                    self.race_time % 60.0 // seconds with milliseconds
+                ));
+                if let Some(date) = self.current_frame().and_then(|frame| frame.date) {
+                    ui.label(format!("UTC {}", date.format("%H:%M:%S%.1f")));
+                }
+                ui.separator();
+
+                if ui.button("START").clicked() {
+                    self.playback.start();
+                    self.set_index(0);
+                    self.led_states.clear(); // Clear LED states when race starts
+                }
+                if ui.button("STOP").clicked() {
+                    self.reset();
+                }
+
+                ui.label("PLAYBACK SPEED");
+                ui.add(egui::Slider::new(&mut self.state.speed, 1..=5));
+                ui.separator();
+                ui.checkbox(&mut self.state.show_outline, "TRACK");
+                ui.checkbox(&mut self.state.show_regions, "SECTORS / DRS");
+                ui.checkbox(&mut self.state.show_positions, "RAW");
+                ui.checkbox(&mut self.state.preview_hardware, "HW PREVIEW");
+                if self.state.preview_hardware || self.playback.has_outputs() {
+                    let budget = self
+                        .correction
+                        .max_current_ma
+                        .map_or(String::new(), |max| format!(" / {:.0}", max));
+                    ui.label(format!("~{:.0}{} mA", self.output_current_ma, budget));
+                }
+                let outputs = self.playback.output_stats();
+                if !outputs.is_empty() {
+                    let details: Vec<String> = outputs
+                        .iter()
+                        .map(|(name, options, stats)| {
+                            format!(
+                                "{} ({} ms): {} sent, {} dropped, {} failed",
+                                name,
+                                options.latency.as_millis(),
+                                stats.sent,
+                                stats.dropped,
+                                stats.errors
+                            )
+                        })
+                        .collect();
+                    let dropped: usize = outputs.iter().map(|(_, _, stats)| stats.dropped).sum();
+                    ui.label(format!("OUT {} dropped", dropped)).on_hover_text(details.join("\n"));
+                }
+                ui.separator();
+                let scheme = self.state.color_scheme;
+                egui::ComboBox::from_label("COLOURS")
+                    .selected_text(scheme.name())
+                    .show_ui(ui, |ui| {
+                        for option in ColorScheme::ALL {
+                            ui.selectable_value(&mut self.state.color_scheme, option, option.name());
+                        }
+                    });
+                if self.state.color_scheme != scheme {
+                    self.recolor();
+                }
+                ui.checkbox(&mut self.state.show_legend, "LEGEND");
+                ui.separator();
+                if ui.button("FIT").clicked() {
+                    self.state.view = ViewState::default();
+                }
+                ui.label(format!("{:.0}%", self.state.view.zoom * 100.0));
+            });
+        });
+
+        self.highlighted.clear();
+        egui::SidePanel::right("legend_panel").show_animated(ctx, self.state.show_legend, |ui| {
+            ui.vertical(|ui| {
+                let style = ui.style_mut();
+                style
+                    .text_styles
+                    .get_mut(&egui::TextStyle::Body)
+                    .unwrap()
+                    .size = 8.0; // Set the font size to 8.0 (or any other size you prefer)
+
+                let shift = ui.input(|i| i.modifiers.shift);
+                let mut clicked = None;
+                let mut picked = None;
+                for driver in &self.driver_info {
+                    let hidden = self.state.hidden_drivers.contains(&driver.number);
+                    let text = egui::RichText::new(format!(
+                        "{}: {} ({}){}",
+                        driver.number,
+                        driver.name,
+                        driver.team,
+                        self.driver_location(driver.number)
+                    ));
+                    ui.horizontal(|ui| {
+                        let response = ui
+                            .add(egui::Label::new(if hidden { text.weak() } else { text }).sense(egui::Sense::click()))
+                            .on_hover_text("Click to show or hide, shift-click to show only this driver");
+                        if response.clicked() {
+                            clicked = Some(driver.number);
+                        }
+                        if response.hovered() {
+                            self.highlighted.push(driver.number);
+                        }
+                        let mut rgb = [driver.color.r, driver.color.g, driver.color.b];
+                        if egui::color_picker::color_edit_button_srgb(ui, &mut rgb).changed() {
+                            picked = Some((driver.number, Rgb::from_rgb(rgb[0], rgb[1], rgb[2])));
+                        }
+                        ui.add_space(5.0); // Space between legend items
+                    });
+                }
+                if let Some(number) = clicked {
+                    self.toggle_driver(number, shift);
+                }
+                if let Some((number, color)) = picked {
+                    self.state.color_overrides.insert(number, color);
+                    self.recolor();
+                }
+                if !self.state.color_overrides.is_empty() && ui.button("RESET COLOURS").clicked() {
+                    self.state.color_overrides.clear();
+                    self.recolor();
+                }
+
+                ui.separator();
+                let mut teams: Vec<(&str, Rgb)> = Vec::new();
+                for driver in &self.driver_info {
+                    if !teams.iter().any(|(team, _)| *team == driver.team) {
+                        teams.push((driver.team, driver.color));
+                    }
+                }
+                let mut clicked_team = None;
+                ui.horizontal_wrapped(|ui| {
+                    for (team, color) in teams {
+                        let members = self.driver_info.iter().filter(|d| d.team == team);
+                        let all_hidden = members.clone().all(|d| self.state.hidden_drivers.contains(&d.number));
+                        let text = egui::RichText::new(team).color(to_color32(color));
+                        let response = ui.add(
+                            egui::Label::new(if all_hidden { text.weak().strikethrough() } else { text })
+                                .sense(egui::Sense::click()),
+                        );
+                        if response.clicked() {
+                            clicked_team = Some(team);
+                        }
+                        if response.hovered() {
+                            self.highlighted.extend(members.map(|d| d.number));
+                        }
+                    }
+                });
+                if let Some(team) = clicked_team {
+                    self.toggle_team(team);
+                }
+                if self.playback.has_outputs() {
+                    ui.checkbox(&mut self.state.hide_on_outputs, "Hide on hardware too");
+                }
+
+                if !self.layout.sectors.is_empty() {
+                    ui.separator();
+                    ui.label(format!("{} ({})", self.layout.circuit, self.layout.id));
+                    for sector in 1..=self.layout.sectors.len() {
+                        ui.label(format!(
+                            "Sector {}: {} LEDs",
+                            sector,
+                            self.layout.leds_in_sector(sector).len()
+                        ));
+                    }
+                }
+            });
+        });
+
+        egui::CentralPanel::default().show(ctx, |ui| {
+            let (response, painter) = ui.allocate_painter(ui.available_size(), egui::Sense::click_and_drag());
+            self.handle_view_input(ui, &response);
+            let transform = TrackTransform::new(&self.layout, response.rect, &self.state.view);
+
+            if self.state.show_outline {
+                self.draw_track_outline(&painter, &transform);
+            }
+            if self.state.show_regions {
+                self.draw_region_underlay(&painter, &transform);
+            }
+
+            let size = egui::vec2(transform.led_size, transform.led_size);
+            for coord in &self.layout.leds {
+                let color = self.led_states.get(&coord.led_number).copied().unwrap_or(egui::Color32::BLACK);
+                painter.rect_filled(
+                    egui::Rect::from_center_size(transform.to_screen(coord), size),
+                    egui::Rounding::same(0.0),
+                    color,
+                );
+            }
+
+            // Pulsing outline around the LEDs of drivers hovered in the legend
+            let pulse = 0.5 + 0.5 * (ctx.input(|i| i.time) * 6.0).sin() as f32;
+            for driver in self.current_frame().map_or(&[][..], |frame| &frame.drivers[..]) {
+                if !self.highlighted.contains(&driver.driver_number) {
+                    continue;
+                }
+                if let Some(coord) = self.layout.coordinate(driver.led_num) {
+                    painter.rect_stroke(
+                        egui::Rect::from_center_size(transform.to_screen(coord), size * (1.3 + 0.4 * pulse)),
+                        egui::Rounding::same(2.0),
+                        egui::Stroke::new(2.0, egui::Color32::WHITE.gamma_multiply(0.4 + 0.6 * pulse)),
+                    );
+                }
+            }
+
+            if self.state.show_positions {
+                self.draw_position_overlay(&painter, &transform);
+            }
+        });
+
+        // Playback repaints on every new frame; only animations need more
+        let animating = self.state.show_positions && self.playback.is_playing() && !self.playback.is_finished();
+        if !self.highlighted.is_empty() || animating {
+            ctx.request_repaint();
+        }
+        // Without a playback thread the window drives playback
+        #[cfg(target_arch = "wasm32")]
+        if let Some(wait) = self.playback.poll() {
+            ctx.request_repaint_after(wait);
+        }
+    }
+
+    fn save(&mut self, storage: &mut dyn eframe::Storage) {
+        eframe::set_value(storage, eframe::APP_KEY, &self.state);
+    }
+}
+
+// Maps layout coordinates into the track panel: one scale for both axes so
+// the circuit keeps its shape, centred, then the view zoom and pan on top
+pub(crate) struct TrackTransform {
+    center: (f64, f64),       // Middle of the layout bounds
+    origin: egui::Pos2,       // Screen position of `center`
+    scale: f32,               // Points per layout unit
+    pub(crate) led_size: f32, // Side of an LED square in points
+}
+
+impl TrackTransform {
+    pub(crate) fn new(layout: &TrackLayout, rect: egui::Rect, view: &ViewState) -> TrackTransform {
+        // The outline can run slightly outside the LEDs, keep it in view too
+        let points = layout.leds.iter().map(|coord| (coord.x_led, coord.y_led)).chain(layout.outline.iter().copied());
+        let (min_x, max_x, min_y, max_y) = points.fold(
+            (f64::INFINITY, f64::NEG_INFINITY, f64::INFINITY, f64::NEG_INFINITY),
+            |(min_x, max_x, min_y, max_y), (x, y)| (min_x.min(x), max_x.max(x), min_y.min(y), max_y.max(y)),
+        );
+        let width = (max_x - min_x).max(1.0);
+        let height = (max_y - min_y).max(1.0);
+
+        let fit = ((rect.width() - 2.0 * VIEW_MARGIN) as f64 / width)
+            .min((rect.height() - 2.0 * VIEW_MARGIN) as f64 / height)
+            .max(0.0) as f32;
+        let scale = fit * view.zoom;
+
+        // LEDs fill most of the gap to their neighbour, whatever the board density
+        let spacing = mean_led_spacing(&layout.leds) as f32 * scale;
+        let led_size = (spacing * 0.7).clamp(MIN_LED_SIZE, MAX_LED_SIZE * view.zoom);
+
+        TrackTransform {
+            center: ((min_x + max_x) / 2.0, (min_y + max_y) / 2.0),
+            origin: rect.center() + egui::vec2(view.pan[0], view.pan[1]),
+            scale,
+            led_size,
+        }
+    }
+
+    // Screen centre of an LED
+    pub(crate) fn to_screen(&self, coord: &LedCoordinate) -> egui::Pos2 {
+        self.to_screen_point(coord.x_led, coord.y_led)
+    }
+
+    // Layout y grows upwards, screen y downwards
+    fn to_screen_point(&self, x: f64, y: f64) -> egui::Pos2 {
+        self.origin
+            + egui::vec2(
+                (x - self.center.0) as f32 * self.scale,
+                -(y - self.center.1) as f32 * self.scale,
+            )
+    }
+}
+
+fn raw_position(frame: &UpdateFrame, driver_number: u32) -> Option<&RawPosition> {
+    frame.positions.iter().find(|p| p.driver_number == driver_number)
+}
+
+// Segment of a closed polyline whose start point is nearest to `point`
+fn nearest_segment(polyline: &[(f64, f64)], point: (f64, f64)) -> Option<((f64, f64), (f64, f64))> {
+    let distance = |p: &(f64, f64)| (p.0 - point.0).powi(2) + (p.1 - point.1).powi(2);
+    let index = (0..polyline.len()).min_by(|&a, &b| distance(&polyline[a]).total_cmp(&distance(&polyline[b])))?;
+    Some((polyline[index], polyline[(index + 1) % polyline.len()]))
+}
+
+// Mean distance between consecutively numbered LEDs
+fn mean_led_spacing(leds: &[LedCoordinate]) -> f64 {
+    let distances: Vec<f64> = leds
+        .windows(2)
+        .map(|pair| ((pair[1].x_led - pair[0].x_led).powi(2) + (pair[1].y_led - pair[0].y_led).powi(2)).sqrt())
+        .collect();
+    if distances.is_empty() {
+        return 0.0;
+    }
+    distances.iter().sum::<f64>() / distances.len() as f64
+}
+
+pub(crate) fn to_color32(color: Rgb) -> egui::Color32 {
+    egui::Color32::from_rgb(color.r, color.g, color.b)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn layout() -> TrackLayout {
+        let leds = [(0.0, 0.0), (200.0, 0.0), (200.0, 100.0), (0.0, 100.0)]
+            .iter()
+            .enumerate()
+            .map(|(i, &(x_led, y_led))| LedCoordinate { x_led, y_led, led_number: i + 1 })
+            .collect();
+        TrackLayout::from_coordinates("test", "Test", leds)
+    }
+
+    #[test]
+    fn track_is_fitted_centred_and_flipped() {
+        let layout = layout();
+        let rect = egui::Rect::from_min_size(egui::pos2(0.0, 0.0), egui::vec2(460.0, 460.0));
+        let transform = TrackTransform::new(&layout, rect, &ViewState::default());
+
+        // 200 units wide in 400 points, so 2 points per unit
+        assert_eq!(transform.to_screen(&layout.leds[0]), egui::pos2(30.0, 330.0));
+        assert_eq!(transform.to_screen(&layout.leds[2]), egui::pos2(430.0, 130.0));
+
+        let view = ViewState { zoom: 2.0, pan: [10.0, -5.0] };
+        let zoomed = TrackTransform::new(&layout, rect, &view);
+        assert_eq!(zoomed.to_screen(&layout.leds[0]), egui::pos2(-160.0, 425.0));
+    }
+
+    #[test]
+    fn restore_without_storage_gives_defaults() {
+        let state = UiState::restore(None);
+        assert_eq!(state.version, UI_STATE_VERSION);
+        assert_eq!(state.speed, 1);
+        assert_eq!(state.view.zoom, 1.0);
+        assert!(state.hidden_drivers.is_empty());
+    }
+}
@@ -0,0 +1,234 @@
+use serde::{Deserialize, Serialize};
+use std::error::Error as StdError;
+use std::fs;
+use std::io;
+use std::path::Path;
+
+use crate::driver_info::{DriverInfo, Rgb};
+use crate::frames::UpdateFrame;
+use crate::layout::TrackLayout;
+
+// Brightness of cars in the pit lane when the layout has no pit lane LEDs
+const PIT_DIM_FACTOR: f32 = 0.3;
+
+// Anything that can show a frame of LED colours; outputs are driven from
+// their own thread by `sinks::OutputSinks`
+pub trait LedOutput: Send {
+    fn send(&mut self, colors: &[Rgb]) -> io::Result<()>;
+}
+
+// Colour of every LED (index 0 is LED 1) for a frame; unlit LEDs are black
+pub fn frame_colors(frame: &UpdateFrame, layout: &TrackLayout, driver_info: &[DriverInfo]) -> Vec<Rgb> {
+    let led_count = layout.leds.iter().map(|coord| coord.led_number).max().unwrap_or(0);
+    let mut colors = vec![Rgb::BLACK; led_count];
+
+    for driver in &frame.drivers {
+        let Some(slot) = driver.led_num.checked_sub(1).and_then(|index| colors.get_mut(index)) else {
+            continue;
+        };
+        let mut color = driver_info
+            .iter()
+            .find(|d| d.number == driver.driver_number)
+            .map_or(Rgb::WHITE, |d| d.color);
+        // Without pit lane LEDs, pitting cars sit dimmed on the nearest track LED
+        if driver.pit_duration.is_some() && layout.pit_lane.is_none() {
+            color = color.scaled(PIT_DIM_FACTOR);
+        }
+        *slot = color;
+    }
+    colors
+}
+
+// Turns screen colours into LED drive values: white balance, gamma, a global
+// brightness limit and finally dimming to stay within the supply's current.
+// The default changes nothing.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ColorCorrection {
+    pub gamma: f32, // About 2.8 for WS2812 and SK6812
+    pub white_balance: [f32; 3], // Per channel scale, red, green, blue
+    pub brightness: f32, // 0.0..=1.0
+    pub max_current_ma: Option<f32>, // Budget for the whole board
+    pub channel_current_ma: f32, // Draw of one channel at full drive
+    pub idle_current_ma: f32, // Draw of one LED when dark
+}
+
+impl Default for ColorCorrection {
+    fn default() -> ColorCorrection {
+        ColorCorrection {
+            gamma: 1.0,
+            white_balance: [1.0, 1.0, 1.0],
+            brightness: 1.0,
+            max_current_ma: None,
+            channel_current_ma: 20.0,
+            idle_current_ma: 1.0,
+        }
+    }
+}
+
+impl ColorCorrection {
+    pub fn load(path: &Path) -> Result<ColorCorrection, Box<dyn StdError>> {
+        let file = fs::File::open(path)?;
+        Ok(serde_json::from_reader(file)?)
+    }
+
+    pub fn apply(&self, colors: &[Rgb]) -> Vec<Rgb> {
+        let brightness = self.brightness.clamp(0.0, 1.0);
+        let correct = |channel: u8, balance: f32| {
+            let linear = (channel as f32 / 255.0 * balance).clamp(0.0, 1.0).powf(self.gamma);
+            (linear * brightness * 255.0).round() as u8
+        };
+        let corrected: Vec<Rgb> = colors
+            .iter()
+            .map(|color| {
+                let [r, g, b] = self.white_balance;
+                Rgb::from_rgb(correct(color.r, r), correct(color.g, g), correct(color.b, b))
+            })
+            .collect();
+
+        // Current is roughly linear in the drive values, so one scale fits the budget
+        let Some(budget) = self.max_current_ma else {
+            return corrected;
+        };
+        let idle = self.idle_current_ma * colors.len() as f32;
+        let current = self.estimated_current_ma(&corrected);
+        if current <= budget || current <= idle {
+            return corrected;
+        }
+        let factor = ((budget - idle) / (current - idle)).max(0.0);
+        corrected.into_iter().map(|color| color.scaled(factor)).collect()
+    }
+
+    // Board current for already corrected drive values
+    pub fn estimated_current_ma(&self, drive: &[Rgb]) -> f32 {
+        let channels: u32 = drive.iter().map(|c| c.r as u32 + c.g as u32 + c.b as u32).sum();
+        channels as f32 / 255.0 * self.channel_current_ma + self.idle_current_ma * drive.len() as f32
+    }
+}
+
+// Approximate on-screen appearance of LED drive values: LEDs are linear in
+// their drive value, screens expect sRGB encoded values
+pub fn preview_color(drive: Rgb) -> Rgb {
+    let encode = |channel: u8| {
+        let linear = channel as f32 / 255.0;
+        let srgb = if linear <= 0.003_130_8 {
+            linear * 12.92
+        } else {
+            1.055 * linear.powf(1.0 / 2.4) - 0.055
+        };
+        (srgb * 255.0).round().clamp(0.0, 255.0) as u8
+    };
+    Rgb::from_rgb(encode(drive.r), encode(drive.g), encode(drive.b))
+}
+
+// Sends each frame as one datagram in the `led_protocol` format
+#[cfg(all(feature = "hardware-serial", not(target_arch = "wasm32")))]
+pub struct UdpOutput {
+    socket: std::net::UdpSocket,
+}
+
+#[cfg(all(feature = "hardware-serial", not(target_arch = "wasm32")))]
+impl UdpOutput {
+    pub fn connect(addr: &str) -> io::Result<UdpOutput> {
+        use std::net::{SocketAddr, ToSocketAddrs, UdpSocket};
+
+        let target = addr
+            .to_socket_addrs()?
+            .next()
+            .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, format!("no address for {}", addr)))?;
+        let local: SocketAddr = if target.is_ipv4() { "0.0.0.0:0" } else { "[::]:0" }.parse().unwrap();
+        let socket = UdpSocket::bind(local)?;
+        socket.connect(target)?;
+        Ok(UdpOutput { socket })
+    }
+}
+
+#[cfg(all(feature = "hardware-serial", not(target_arch = "wasm32")))]
+impl LedOutput for UdpOutput {
+    fn send(&mut self, colors: &[Rgb]) -> io::Result<()> {
+        self.socket.send(&crate::led_protocol::encode_frame(colors)).map(|_| ())
+    }
+}
+
+// Records frames to a file: milliseconds since the recording started (u32
+// LE) followed by the frame in the `led_protocol` format
+#[cfg(all(feature = "hardware-serial", not(target_arch = "wasm32")))]
+pub struct RecorderOutput {
+    file: io::BufWriter<fs::File>,
+    started: std::time::Instant,
+}
+
+#[cfg(all(feature = "hardware-serial", not(target_arch = "wasm32")))]
+impl RecorderOutput {
+    pub fn create(path: &Path) -> io::Result<RecorderOutput> {
+        Ok(RecorderOutput {
+            file: io::BufWriter::new(fs::File::create(path)?),
+            started: std::time::Instant::now(),
+        })
+    }
+}
+
+#[cfg(all(feature = "hardware-serial", not(target_arch = "wasm32")))]
+impl LedOutput for RecorderOutput {
+    fn send(&mut self, colors: &[Rgb]) -> io::Result<()> {
+        use std::io::Write;
+
+        let millis = self.started.elapsed().as_millis().min(u32::MAX as u128) as u32;
+        self.file.write_all(&millis.to_le_bytes())?;
+        self.file.write_all(&crate::led_protocol::encode_frame(colors))
+    }
+}
+
+// Writes frames in the `led_protocol` format to a serial port
+#[cfg(all(feature = "hardware-serial", not(target_arch = "wasm32")))]
+pub struct SerialOutput {
+    port: Box<dyn serialport::SerialPort>,
+}
+
+#[cfg(all(feature = "hardware-serial", not(target_arch = "wasm32")))]
+impl SerialOutput {
+    pub fn open(path: &str, baud_rate: u32) -> Result<SerialOutput, serialport::Error> {
+        let port = serialport::new(path, baud_rate)
+            .timeout(std::time::Duration::from_millis(100))
+            .open()?;
+        Ok(SerialOutput { port })
+    }
+}
+
+#[cfg(all(feature = "hardware-serial", not(target_arch = "wasm32")))]
+impl LedOutput for SerialOutput {
+    fn send(&mut self, colors: &[Rgb]) -> io::Result<()> {
+        self.port.write_all(&crate::led_protocol::encode_frame(colors))?;
+        self.port.flush()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn color_correction_limits_current() {
+        let white = vec![Rgb::WHITE; 10];
+        assert_eq!(ColorCorrection::default().apply(&white), white);
+
+        // 10 white LEDs draw 610 mA, 10 of them idle
+        let limited = ColorCorrection { max_current_ma: Some(310.0), ..ColorCorrection::default() };
+        let corrected = limited.apply(&white);
+        assert!((limited.estimated_current_ma(&corrected) - 310.0).abs() < 5.0);
+    }
+
+    #[cfg(all(feature = "hardware-serial", not(target_arch = "wasm32")))]
+    #[test]
+    fn udp_output_sends_one_frame_per_datagram() {
+        let receiver = std::net::UdpSocket::bind("127.0.0.1:0").unwrap();
+        receiver.set_read_timeout(Some(std::time::Duration::from_secs(5))).unwrap();
+        let mut output = UdpOutput::connect(&receiver.local_addr().unwrap().to_string()).unwrap();
+
+        let colors = [Rgb::from_rgb(1, 2, 3), Rgb::BLACK];
+        output.send(&colors).unwrap();
+        let mut datagram = [0u8; 64];
+        let length = receiver.recv(&mut datagram).unwrap();
+        assert_eq!(datagram[..length], crate::led_protocol::encode_frame(&colors));
+    }
+}
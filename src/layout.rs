@@ -0,0 +1,229 @@
+use serde::{Deserialize, Serialize};
+use std::error::Error as StdError;
+use std::fs;
+use std::path::Path;
+
+use crate::led_coords::{read_coordinates, LedCoordinate};
+
+/// Inclusive range of LED numbers. A range whose `first` is greater than its
+/// `last` wraps around the end of the board (e.g. 94..=2 on a 96 LED track).
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct LedRange {
+    pub first: usize,
+    pub last: usize,
+}
+
+impl LedRange {
+    pub fn new(first: usize, last: usize) -> LedRange {
+        LedRange { first, last }
+    }
+
+    pub fn contains(&self, led_number: usize) -> bool {
+        if self.first <= self.last {
+            led_number >= self.first && led_number <= self.last
+        } else {
+            led_number >= self.first || led_number <= self.last
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Corner {
+    pub name: String,
+    pub leds: LedRange,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TrackLayout {
+    pub id: String,
+    pub circuit: String,
+    pub leds: Vec<LedCoordinate>,
+    #[serde(default)]
+    pub sectors: Vec<LedRange>, // Index 0 is sector 1
+    #[serde(default)]
+    pub drs_zones: Vec<LedRange>,
+    #[serde(default)]
+    pub pit_lane: Option<LedRange>,
+    #[serde(default)]
+    pub start_finish_led: Option<usize>,
+    #[serde(default)]
+    pub corners: Vec<Corner>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub outline: Vec<(f64, f64)>, // Closed circuit centreline in track units, e.g. a reference lap
+}
+
+impl TrackLayout {
+    /// Builds a layout with no metadata around a plain list of coordinates.
+    pub fn from_coordinates(id: &str, circuit: &str, leds: Vec<LedCoordinate>) -> TrackLayout {
+        TrackLayout {
+            id: id.to_string(),
+            circuit: circuit.to_string(),
+            leds,
+            sectors: Vec::new(),
+            drs_zones: Vec::new(),
+            pit_lane: None,
+            start_finish_led: None,
+            corners: Vec::new(),
+            outline: Vec::new(),
+        }
+    }
+
+    pub fn load(path: &Path) -> Result<TrackLayout, Box<dyn StdError>> {
+        let file = fs::File::open(path)?;
+        Ok(serde_json::from_reader(file)?)
+    }
+
+    pub fn save(&self, path: &Path) -> Result<(), Box<dyn StdError>> {
+        let file = fs::File::create(path)?;
+        serde_json::to_writer_pretty(file, self)?;
+        Ok(())
+    }
+
+    /// Sector number (1-based) containing the LED, if sectors are defined.
+    pub fn sector_of(&self, led_number: usize) -> Option<usize> {
+        self.sectors
+            .iter()
+            .position(|range| range.contains(led_number))
+            .map(|index| index + 1)
+    }
+
+    /// LEDs of the given 1-based sector, in board order.
+    pub fn leds_in_sector(&self, sector: usize) -> Vec<&LedCoordinate> {
+        match sector.checked_sub(1).and_then(|index| self.sectors.get(index)) {
+            Some(range) => self
+                .leds
+                .iter()
+                .filter(|coord| range.contains(coord.led_number))
+                .collect(),
+            None => Vec::new(),
+        }
+    }
+
+    pub fn corner_of(&self, led_number: usize) -> Option<&Corner> {
+        self.corners
+            .iter()
+            .find(|corner| corner.leds.contains(led_number))
+    }
+
+    pub fn is_drs_zone(&self, led_number: usize) -> bool {
+        self.drs_zones
+            .iter()
+            .any(|range| range.contains(led_number))
+    }
+
+    pub fn is_pit_lane(&self, led_number: usize) -> bool {
+        self.pit_lane
+            .is_some_and(|range| range.contains(led_number))
+    }
+
+    pub fn coordinate(&self, led_number: usize) -> Option<&LedCoordinate> {
+        self.leds.iter().find(|coord| coord.led_number == led_number)
+    }
+
+    /// Closed circuit centreline: the stored outline, or otherwise the track
+    /// LEDs joined in board order, leaving out the pit lane.
+    pub fn track_outline(&self) -> Vec<(f64, f64)> {
+        if !self.outline.is_empty() {
+            return self.outline.clone();
+        }
+        let mut leds: Vec<&LedCoordinate> = self
+            .leds
+            .iter()
+            .filter(|coord| !self.is_pit_lane(coord.led_number))
+            .collect();
+        leds.sort_by_key(|coord| coord.led_number);
+        leds.iter().map(|coord| (coord.x_led, coord.y_led)).collect()
+    }
+
+    /// Pit lane LEDs in board order from the pit entry, empty without a pit lane.
+    pub fn pit_lane_path(&self) -> Vec<(f64, f64)> {
+        let Some(range) = self.pit_lane else {
+            return Vec::new();
+        };
+        let mut leds: Vec<&LedCoordinate> = self
+            .leds
+            .iter()
+            .filter(|coord| range.contains(coord.led_number))
+            .collect();
+        // Wrapped ranges continue from LED 1 after the end of the board
+        leds.sort_by_key(|coord| (coord.led_number < range.first, coord.led_number));
+        leds.iter().map(|coord| (coord.x_led, coord.y_led)).collect()
+    }
+}
+
+/// Zandvoort board layout with sector, DRS and corner regions.
+pub fn read_layout() -> Result<TrackLayout, Box<dyn StdError>> {
+    let mut layout = TrackLayout::from_coordinates("zandvoort-96", "Zandvoort", read_coordinates()?);
+
+    layout.sectors = vec![
+        LedRange::new(1, 32),  // Sector 1
+        LedRange::new(33, 64), // Sector 2
+        LedRange::new(65, 96), // Sector 3
+    ];
+    layout.drs_zones = vec![
+        LedRange::new(44, 55), // Back straight
+        LedRange::new(90, 2),  // Main straight, across the line
+    ];
+    layout.start_finish_led = Some(1);
+    layout.corners = vec![
+        corner("Tarzanbocht", 3, 5),
+        corner("Gerlachbocht", 8, 11),
+        corner("Hugenholtzbocht", 23, 26),
+        corner("Hunserug", 31, 34),
+        corner("Rob Slotemakerbocht", 37, 41),
+        corner("Scheivlak", 56, 59),
+        corner("Mastersbocht", 65, 68),
+        corner("Hans Ernstbocht", 78, 80),
+        corner("Arie Luyendykbocht", 86, 89),
+    ];
+
+    Ok(layout)
+}
+
+fn corner(name: &str, first: usize, last: usize) -> Corner {
+    Corner {
+        name: name.to_string(),
+        leds: LedRange::new(first, last),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn ranges_contain_their_ends_and_wrap_past_the_last_led() {
+        let range = LedRange::new(44, 55);
+        assert!(range.contains(44) && range.contains(50) && range.contains(55));
+        assert!(!range.contains(43) && !range.contains(56));
+
+        // From LED 90 over the line to LED 2
+        let wrapping = LedRange::new(90, 2);
+        assert!([90, 96, 1, 2].iter().all(|&led| wrapping.contains(led)));
+        assert!(!wrapping.contains(3) && !wrapping.contains(89) && !wrapping.contains(50));
+
+        assert!(LedRange::new(7, 7).contains(7));
+        assert!(!LedRange::new(7, 7).contains(8));
+    }
+
+    #[test]
+    fn leds_are_found_by_sector() {
+        let mut layout = read_layout().unwrap();
+        assert_eq!(layout.sector_of(1), Some(1));
+        assert_eq!(layout.sector_of(32), Some(1));
+        assert_eq!(layout.sector_of(33), Some(2));
+        assert_eq!(layout.sector_of(96), Some(3));
+        assert_eq!(layout.sector_of(97), None);
+
+        let sector_2: Vec<usize> = layout.leds_in_sector(2).iter().map(|coord| coord.led_number).collect();
+        assert_eq!(sector_2, (33..=64).collect::<Vec<_>>());
+        assert!(layout.leds_in_sector(0).is_empty());
+        assert!(layout.leds_in_sector(4).is_empty());
+
+        // A sector across the start line keeps board order
+        layout.sectors = vec![LedRange::new(95, 2), LedRange::new(3, 94)];
+        assert_eq!(layout.sector_of(96), Some(1));
+        let across: Vec<usize> = layout.leds_in_sector(1).iter().map(|coord| coord.led_number).collect();
+        assert_eq!(across, [1, 2, 95, 96]);
+    }
+}
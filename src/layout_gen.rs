@@ -0,0 +1,133 @@
+use crate::frames::LocationData;
+use crate::led_coords::LedCoordinate;
+
+// Circuit polyline from a lap, dropping repeated positions
+pub fn build_polyline(lap: &[LocationData]) -> Vec<(f64, f64)> {
+    let mut polyline: Vec<(f64, f64)> = Vec::with_capacity(lap.len());
+    for sample in lap {
+        if polyline.last() != Some(&(sample.x, sample.y)) {
+            polyline.push((sample.x, sample.y));
+        }
+    }
+    polyline
+}
+
+// Places `led_count` LEDs evenly by arc length around the closed polyline,
+// with LED 1 sitting `start_offset` track units after the first point.
+pub fn generate_leds(polyline: &[(f64, f64)], led_count: usize, start_offset: f64) -> Vec<LedCoordinate> {
+    if polyline.len() < 2 || led_count == 0 {
+        return Vec::new();
+    }
+
+    // Cumulative distance at the start of each segment, including the closing one
+    let segment_count = polyline.len();
+    let mut cumulative = Vec::with_capacity(segment_count + 1);
+    let mut total = 0.0;
+    cumulative.push(0.0);
+    for i in 0..segment_count {
+        let (x1, y1) = polyline[i];
+        let (x2, y2) = polyline[(i + 1) % segment_count];
+        total += ((x2 - x1).powi(2) + (y2 - y1).powi(2)).sqrt();
+        cumulative.push(total);
+    }
+
+    let spacing = total / led_count as f64;
+    let mut leds = Vec::with_capacity(led_count);
+    let mut segment = 0;
+    for led in 0..led_count {
+        let distance = (start_offset + led as f64 * spacing).rem_euclid(total);
+        // Distances wrap at most once, so restart the segment scan when they do
+        if distance < cumulative[segment] {
+            segment = 0;
+        }
+        while cumulative[segment + 1] < distance {
+            segment += 1;
+        }
+
+        let (x1, y1) = polyline[segment];
+        let (x2, y2) = polyline[(segment + 1) % segment_count];
+        let length = cumulative[segment + 1] - cumulative[segment];
+        let t = if length > 0.0 { (distance - cumulative[segment]) / length } else { 0.0 };
+
+        leds.push(LedCoordinate {
+            x_led: (x1 + (x2 - x1) * t).round(),
+            y_led: (y1 + (y2 - y1) * t).round(),
+            led_number: led + 1,
+        });
+    }
+    leds
+}
+
+// Point of the closed polyline nearest to `point`, anywhere along its
+// segments rather than only at its vertices
+pub fn nearest_on_polyline(polyline: &[(f64, f64)], point: (f64, f64)) -> Option<(f64, f64)> {
+    let squared = |(x, y): (f64, f64)| (x - point.0).powi(2) + (y - point.1).powi(2);
+    (0..polyline.len())
+        .map(|i| {
+            let (x1, y1) = polyline[i];
+            let (x2, y2) = polyline[(i + 1) % polyline.len()];
+            let (dx, dy) = (x2 - x1, y2 - y1);
+            let length_squared = dx * dx + dy * dy;
+            if length_squared == 0.0 {
+                return (x1, y1);
+            }
+            let t = (((point.0 - x1) * dx + (point.1 - y1) * dy) / length_squared).clamp(0.0, 1.0);
+            (x1 + t * dx, y1 + t * dy)
+        })
+        .min_by(|&a, &b| squared(a).total_cmp(&squared(b)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SQUARE: [(f64, f64); 4] = [(0.0, 0.0), (100.0, 0.0), (100.0, 100.0), (0.0, 100.0)];
+
+    fn points(leds: &[LedCoordinate]) -> Vec<(f64, f64)> {
+        leds.iter().map(|led| (led.x_led, led.y_led)).collect()
+    }
+
+    #[test]
+    fn leds_are_spaced_evenly_around_the_closed_loop() {
+        let leds = generate_leds(&SQUARE, 16, 0.0);
+        assert_eq!(leds.len(), 16);
+        assert!(leds.iter().enumerate().all(|(i, led)| led.led_number == i + 1));
+        // 400 units of track, one LED every 25 units, the last on the closing side
+        let expected = [
+            (0.0, 0.0), (25.0, 0.0), (50.0, 0.0), (75.0, 0.0),
+            (100.0, 0.0), (100.0, 25.0), (100.0, 50.0), (100.0, 75.0),
+            (100.0, 100.0), (75.0, 100.0), (50.0, 100.0), (25.0, 100.0),
+            (0.0, 100.0), (0.0, 75.0), (0.0, 50.0), (0.0, 25.0),
+        ];
+        assert_eq!(points(&leds), expected);
+    }
+
+    #[test]
+    fn the_start_offset_moves_led_1_along_the_track() {
+        let leds = generate_leds(&SQUARE, 4, 30.0);
+        assert_eq!(points(&leds), [(30.0, 0.0), (100.0, 30.0), (70.0, 100.0), (0.0, 70.0)]);
+
+        // Offsets wrap around the loop either way
+        let wrapped = generate_leds(&SQUARE, 4, 390.0);
+        assert_eq!(points(&wrapped), [(0.0, 10.0), (90.0, 0.0), (100.0, 90.0), (10.0, 100.0)]);
+        assert_eq!(points(&generate_leds(&SQUARE, 4, -10.0)), points(&wrapped));
+    }
+
+    #[test]
+    fn too_few_points_or_leds_give_no_leds() {
+        assert!(generate_leds(&SQUARE, 0, 0.0).is_empty());
+        assert!(generate_leds(&SQUARE[..1], 10, 0.0).is_empty());
+        assert_eq!(generate_leds(&SQUARE, 1, 0.0).len(), 1);
+        assert_eq!(generate_leds(&SQUARE, 401, 0.0).len(), 401);
+    }
+
+    #[test]
+    fn nearest_points_lie_on_the_segments() {
+        assert_eq!(nearest_on_polyline(&SQUARE, (40.0, -20.0)), Some((40.0, 0.0)));
+        assert_eq!(nearest_on_polyline(&SQUARE, (130.0, 60.0)), Some((100.0, 60.0)));
+        // The closing segment counts too
+        assert_eq!(nearest_on_polyline(&SQUARE, (-5.0, 25.0)), Some((0.0, 25.0)));
+        assert_eq!(nearest_on_polyline(&SQUARE, (110.0, 120.0)), Some((100.0, 100.0)));
+        assert_eq!(nearest_on_polyline(&[], (0.0, 0.0)), None);
+    }
+}
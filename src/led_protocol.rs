@@ -0,0 +1,196 @@
+// Wire format of LED frames sent to the board, over a serial port, as UDP
+// datagrams or in recordings. A frame is:
+//
+//     offset  size       field
+//     0       2          magic, 0xF1 0xED
+//     2       2          LED count N, u16 little endian
+//     4       3 * N      red, green, blue of each LED in board order (LED 1 first)
+//     4 + 3N  1          checksum, the sum of the RGB bytes modulo 256
+//
+// Frames are sent back to back with no other delimiter; a receiver that
+// loses sync looks for the next magic. Values are LED drive values, after
+// any `ColorCorrection`.
+use crate::driver_info::Rgb;
+
+pub const FRAME_MAGIC: [u8; 2] = [0xF1, 0xED];
+// Magic and LED count
+pub const HEADER_LEN: usize = 4;
+
+pub fn encode_frame(colors: &[Rgb]) -> Vec<u8> {
+    let mut bytes = Vec::with_capacity(colors.len() * 3 + HEADER_LEN + 1);
+    bytes.extend_from_slice(&FRAME_MAGIC);
+    bytes.extend_from_slice(&(colors.len() as u16).to_le_bytes());
+
+    let mut checksum: u8 = 0;
+    for color in colors {
+        for byte in [color.r, color.g, color.b] {
+            bytes.push(byte);
+            checksum = checksum.wrapping_add(byte);
+        }
+    }
+    bytes.push(checksum);
+    bytes
+}
+
+// Inverse of `encode_frame` for exactly one frame, `None` if it is malformed
+pub fn decode_frame(bytes: &[u8]) -> Option<Vec<Rgb>> {
+    let (header, rest) = bytes.split_first_chunk::<HEADER_LEN>()?;
+    if header[..2] != FRAME_MAGIC {
+        return None;
+    }
+    let count = u16::from_le_bytes([header[2], header[3]]) as usize;
+    let (payload, checksum) = rest.split_at_checked(count * 3)?;
+    if *checksum != [payload.iter().fold(0u8, |sum, &byte| sum.wrapping_add(byte))] {
+        return None;
+    }
+    Some(
+        payload
+            .chunks_exact(3)
+            .map(|rgb| Rgb::from_rgb(rgb[0], rgb[1], rgb[2]))
+            .collect(),
+    )
+}
+
+// Splits a byte stream into frames, resynchronising on the magic bytes after
+// garbage or a bad checksum
+#[derive(Debug, Default)]
+pub struct FrameDecoder {
+    buffer: Vec<u8>,
+    errors: usize,
+    resyncing: bool, // Skipping bytes since the last good frame
+}
+
+impl FrameDecoder {
+    pub fn new() -> FrameDecoder {
+        FrameDecoder::default()
+    }
+
+    pub fn push(&mut self, bytes: &[u8]) -> Vec<Vec<Rgb>> {
+        self.buffer.extend_from_slice(bytes);
+        let mut frames = Vec::new();
+
+        loop {
+            let Some(start) = self.buffer.windows(2).position(|pair| pair == FRAME_MAGIC) else {
+                // Keep a trailing first magic byte, the second may still arrive
+                let keep = usize::from(self.buffer.last() == Some(&FRAME_MAGIC[0]));
+                if self.buffer.len() > keep {
+                    self.skip(self.buffer.len() - keep);
+                }
+                break;
+            };
+            if start > 0 {
+                self.skip(start);
+            }
+            if self.buffer.len() < HEADER_LEN {
+                break;
+            }
+
+            let count = u16::from_le_bytes([self.buffer[2], self.buffer[3]]) as usize;
+            let length = HEADER_LEN + count * 3 + 1;
+            if self.buffer.len() < length {
+                break;
+            }
+            match decode_frame(&self.buffer[..length]) {
+                Some(colors) => {
+                    frames.push(colors);
+                    self.buffer.drain(..length);
+                    self.resyncing = false;
+                }
+                // Not a frame after all, look for the next magic
+                None => self.skip(1),
+            }
+        }
+        frames
+    }
+
+    // Runs of garbage and corrupt frames skipped so far, each counted once
+    // however many bytes it took to find the next good frame
+    pub fn errors(&self) -> usize {
+        self.errors
+    }
+
+    fn skip(&mut self, length: usize) {
+        if !self.resyncing {
+            self.errors += 1;
+            self.resyncing = true;
+        }
+        self.buffer.drain(..length);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn colors() -> Vec<Rgb> {
+        vec![Rgb::from_rgb(255, 0, 0), Rgb::from_rgb(0, 128, 0), Rgb::from_rgb(1, 2, 3)]
+    }
+
+    #[test]
+    fn frames_round_trip() {
+        let bytes = encode_frame(&colors());
+        assert_eq!(bytes[..HEADER_LEN], [0xF1, 0xED, 3, 0]);
+        assert_eq!(*bytes.last().unwrap(), (255u32 + 128 + 1 + 2 + 3) as u8);
+        assert_eq!(decode_frame(&bytes), Some(colors()));
+        assert_eq!(decode_frame(&encode_frame(&[])), Some(Vec::new()));
+    }
+
+    #[test]
+    fn malformed_frames_are_rejected() {
+        let bytes = encode_frame(&colors());
+
+        let mut bad_magic = bytes.clone();
+        bad_magic[1] = 0xEE;
+        assert_eq!(decode_frame(&bad_magic), None);
+
+        let mut bad_checksum = bytes.clone();
+        bad_checksum[5] ^= 1;
+        assert_eq!(decode_frame(&bad_checksum), None);
+
+        assert_eq!(decode_frame(&bytes[..bytes.len() - 1]), None);
+        assert_eq!(decode_frame(&bytes[..3]), None);
+    }
+
+    #[test]
+    fn frames_split_across_writes_are_joined() {
+        let mut stream = encode_frame(&colors());
+        stream.extend(encode_frame(&[Rgb::WHITE]));
+
+        let mut decoder = FrameDecoder::new();
+        let mut frames = Vec::new();
+        for byte in &stream {
+            frames.extend(decoder.push(&[*byte]));
+        }
+        assert_eq!(frames, vec![colors(), vec![Rgb::WHITE]]);
+        assert_eq!(decoder.errors(), 0);
+    }
+
+    #[test]
+    fn leading_garbage_is_skipped() {
+        let mut decoder = FrameDecoder::new();
+        assert!(decoder.push(&[0x00, 0xED, 0xF1]).is_empty());
+        // The trailing 0xF1 turns out to be the start of the magic
+        let frame = encode_frame(&colors());
+        assert_eq!(decoder.push(&frame[1..]), vec![colors()]);
+        assert_eq!(decoder.errors(), 1);
+    }
+
+    #[test]
+    fn a_corrupt_frame_is_one_error() {
+        let mut corrupt = encode_frame(&colors());
+        corrupt[5] ^= 1;
+        let mut stream = corrupt;
+        stream.extend_from_slice(&[0x00, 0xF1, 0x42]);
+        stream.extend(encode_frame(&colors()));
+
+        let mut decoder = FrameDecoder::new();
+        assert_eq!(decoder.push(&stream), vec![colors()]);
+        assert_eq!(decoder.errors(), 1);
+
+        // The next run of garbage counts again
+        stream = vec![0x13, 0x37];
+        stream.extend(encode_frame(&colors()));
+        assert_eq!(decoder.push(&stream), vec![colors()]);
+        assert_eq!(decoder.errors(), 2);
+    }
+}
@@ -0,0 +1,44 @@
+// lib.rs
+// GUI-free core: data models, LED mapping and frame generation. OpenF1
+// fetching, firmware export, serial output and the egui simulator are behind
+// the `openf1`, `export`, `hardware-serial` and `gui` features; with `gui`
+// on wasm32 the `web` module runs the simulator in the browser.
+// `hardware-serial` also brings the `led_protocol` frame format spoken by
+// every board output and `mock_board`, which emulates the board natively for
+// testing them.
+pub mod calibration;
+pub mod colors;
+pub mod delta;
+pub mod diagnostics;
+pub mod driver_info;
+#[cfg(feature = "export")]
+pub mod firmware;
+pub mod frame_store;
+pub mod frames;
+pub mod hardware;
+pub mod layout;
+pub mod layout_gen;
+#[cfg(feature = "hardware-serial")]
+pub mod led_protocol;
+pub mod led_coords;
+pub mod location_parser;
+#[cfg(all(feature = "hardware-serial", not(target_arch = "wasm32")))]
+pub mod mock_board;
+#[cfg(feature = "openf1")]
+pub mod openf1;
+pub mod pit;
+pub mod playback;
+pub mod samples;
+pub mod session;
+pub mod sinks;
+pub mod timestamp;
+
+#[cfg(feature = "gui")]
+pub mod gui;
+#[cfg(all(feature = "gui", target_arch = "wasm32"))]
+pub mod web;
+
+pub use frames::{
+    generate_update_frames, DriverData, FrameEncoding, FrameStream, LedMapper, LocationData, UpdateFrame,
+    VisualizationData,
+};
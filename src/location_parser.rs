@@ -0,0 +1,383 @@
+use chrono::{DateTime, Utc};
+use serde::Deserialize;
+use std::borrow::Cow;
+use std::error::Error as StdError;
+
+use crate::frames::LocationData;
+use crate::timestamp;
+
+// Reads a JSON array of location samples as its bytes arrive, so a response
+// never has to be held whole: each record is decoded as soon as all of it is
+// there and only the unfinished one is kept. Records are flat objects, which
+// are read in a single pass; anything else is handed to serde_json. Records
+// that cannot be read are skipped and counted rather than failing the
+// response.
+#[derive(Debug, Default)]
+pub struct LocationParser {
+    buffer: Vec<u8>, // Starts at the unfinished record, if any
+    started: bool,
+    closed: bool,
+    expect: Expect,
+    skipped: SkippedRecords,
+}
+
+// What may come next inside the array
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+enum Expect {
+    #[default]
+    FirstRecord, // A record or the end, right after `[`
+    Record, // After a comma
+    Separator, // A comma or the end, after a record
+}
+
+// Records left out of a response because they could not be read
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct SkippedRecords {
+    pub count: usize,
+    pub first_error: Option<String>,
+}
+
+impl LocationParser {
+    pub fn new() -> LocationParser {
+        LocationParser::default()
+    }
+
+    // Feeds the next bytes of the response, calling `sample` for every record
+    // they complete
+    pub fn push(&mut self, bytes: &[u8], mut sample: impl FnMut(LocationData)) -> Result<(), Box<dyn StdError>> {
+        self.buffer.extend_from_slice(bytes);
+
+        let mut pos = 0;
+        while let Some(&byte) = self.buffer.get(pos) {
+            match byte {
+                b' ' | b'\t' | b'\r' | b'\n' => pos += 1,
+                _ if self.closed => return Err("Unexpected data after the end of the array".into()),
+                b'[' if !self.started => {
+                    self.started = true;
+                    pos += 1;
+                }
+                _ if !self.started => return Err("Expected a JSON array of location samples".into()),
+                b',' if self.expect == Expect::Separator => {
+                    self.expect = Expect::Record;
+                    pos += 1;
+                }
+                b']' if self.expect != Expect::Record => {
+                    self.closed = true;
+                    pos += 1;
+                }
+                b'{' if self.expect == Expect::Separator => return Err("Missing ',' between records".into()),
+                b'{' => match read_record(&self.buffer[pos..]) {
+                    Some((record, len)) => {
+                        match record {
+                            Ok(data) => sample(data),
+                            Err(error) => {
+                                self.skipped.count += 1;
+                                self.skipped.first_error.get_or_insert(error);
+                            }
+                        }
+                        pos += len;
+                        self.expect = Expect::Separator;
+                    }
+                    None => break,
+                },
+                _ => return Err(format!("Unexpected '{}' between records", byte as char).into()),
+            }
+        }
+
+        self.buffer.drain(..pos);
+        Ok(())
+    }
+
+    // Checks that the response ended with the array and returns the records
+    // that were skipped
+    pub fn finish(self) -> Result<SkippedRecords, Box<dyn StdError>> {
+        if self.closed {
+            Ok(self.skipped)
+        } else {
+            Err("Location response ended before the end of the array".into())
+        }
+    }
+}
+
+// Decodes the record at the start of `bytes` and returns its length, `None`
+// until all of it has arrived
+fn read_record(bytes: &[u8]) -> Option<(Result<LocationData, String>, usize)> {
+    match flat_record(bytes) {
+        Ok((fields, len)) => Some((fields.decode(), len)),
+        Err(Stop::Incomplete) => None,
+        Err(Stop::Unusual) => {
+            let len = record_end(bytes)?;
+            let record = serde_json::from_slice::<Record>(&bytes[..len])
+                .map_err(|e| e.to_string())
+                .and_then(|record| record.decode());
+            Some((record, len))
+        }
+    }
+}
+
+// Why `flat_record` gave up
+enum Stop {
+    Incomplete,
+    Unusual, // Nested values, escapes or invalid JSON
+}
+
+// The raw values of the fields a sample needs; strings keep their quotes
+#[derive(Default)]
+struct Fields<'a> {
+    x: Option<&'a str>,
+    y: Option<&'a str>,
+    date: Option<&'a str>,
+    driver_number: Option<&'a str>,
+}
+
+impl Fields<'_> {
+    fn decode(&self) -> Result<LocationData, String> {
+        let date = field(self.date, "date")?;
+        let date = date
+            .strip_prefix('"')
+            .and_then(|date| date.strip_suffix('"'))
+            .ok_or_else(|| format!("Invalid date {}", date))?;
+        Ok(LocationData {
+            x: number(self.x, "x")?,
+            y: number(self.y, "y")?,
+            date: parse_date(date)?,
+            driver_number: number(self.driver_number, "driver_number")?,
+        })
+    }
+}
+
+fn field<'a>(value: Option<&'a str>, name: &str) -> Result<&'a str, String> {
+    value.ok_or_else(|| format!("Missing field `{}`", name))
+}
+
+fn parse_date(date: &str) -> Result<DateTime<Utc>, String> {
+    timestamp::parse_utc(date).map_err(|e| format!("Invalid date {}: {}", date, e))
+}
+
+fn number<T: std::str::FromStr>(value: Option<&str>, name: &str) -> Result<T, String> {
+    let value = field(value, name)?;
+    value.parse().map_err(|_| format!("Invalid {} {}", name, value))
+}
+
+// Reads a record that is a flat object without escapes, the way OpenF1 sends
+// them, and returns its fields and length
+fn flat_record(bytes: &[u8]) -> Result<(Fields<'_>, usize), Stop> {
+    let mut fields = Fields::default();
+    let mut pos = skip_whitespace(bytes, 1); // After the opening brace
+    if bytes.get(pos) == Some(&b'}') {
+        return Ok((fields, pos + 1));
+    }
+    loop {
+        match bytes.get(pos) {
+            None => return Err(Stop::Incomplete),
+            Some(b'"') => {}
+            Some(_) => return Err(Stop::Unusual),
+        }
+        let key_end = string_end(bytes, pos)?;
+        let key = &bytes[pos + 1..key_end - 1];
+
+        pos = skip_whitespace(bytes, key_end);
+        match bytes.get(pos) {
+            None => return Err(Stop::Incomplete),
+            Some(b':') => pos = skip_whitespace(bytes, pos + 1),
+            Some(_) => return Err(Stop::Unusual),
+        }
+        let value_end = match bytes.get(pos) {
+            None => return Err(Stop::Incomplete),
+            Some(b'"') => string_end(bytes, pos)?,
+            Some(b'{' | b'[') => return Err(Stop::Unusual),
+            Some(_) => {
+                let len = bytes[pos..]
+                    .iter()
+                    .position(|b| matches!(b, b',' | b'}' | b' ' | b'\t' | b'\r' | b'\n'))
+                    .ok_or(Stop::Incomplete)?;
+                pos + len
+            }
+        };
+        let value = std::str::from_utf8(&bytes[pos..value_end]).map_err(|_| Stop::Unusual)?;
+        match key {
+            b"x" => fields.x = Some(value),
+            b"y" => fields.y = Some(value),
+            b"date" => fields.date = Some(value),
+            b"driver_number" => fields.driver_number = Some(value),
+            _ => {}
+        }
+
+        // Fields are separated by exactly one comma
+        pos = skip_whitespace(bytes, value_end);
+        match bytes.get(pos) {
+            None => return Err(Stop::Incomplete),
+            Some(b',') => pos = skip_whitespace(bytes, pos + 1),
+            Some(b'}') => return Ok((fields, pos + 1)),
+            Some(_) => return Err(Stop::Unusual),
+        }
+    }
+}
+
+fn skip_whitespace(bytes: &[u8], pos: usize) -> usize {
+    pos + bytes[pos.min(bytes.len())..]
+        .iter()
+        .take_while(|b| matches!(b, b' ' | b'\t' | b'\r' | b'\n'))
+        .count()
+}
+
+// Position after the closing quote of the string starting at `start`
+fn string_end(bytes: &[u8], start: usize) -> Result<usize, Stop> {
+    let len = bytes[start + 1..]
+        .iter()
+        .position(|&b| b == b'"' || b == b'\\')
+        .ok_or(Stop::Incomplete)?;
+    match bytes[start + 1 + len] {
+        b'"' => Ok(start + len + 2),
+        _ => Err(Stop::Unusual),
+    }
+}
+
+// Length of the JSON object at the start of `bytes`, `None` if it is cut off
+fn record_end(bytes: &[u8]) -> Option<usize> {
+    let (mut depth, mut in_string, mut escaped) = (0, false, false);
+    for (i, &byte) in bytes.iter().enumerate() {
+        if in_string {
+            if escaped {
+                escaped = false;
+            } else if byte == b'\\' {
+                escaped = true;
+            } else if byte == b'"' {
+                in_string = false;
+            }
+            continue;
+        }
+        match byte {
+            b'"' => in_string = true,
+            b'{' | b'[' => depth += 1,
+            b'}' | b']' => {
+                depth -= 1;
+                if depth == 0 {
+                    return Some(i + 1);
+                }
+            }
+            _ => {}
+        }
+    }
+    None
+}
+
+// A record serde_json has to read; other fields are skipped
+#[derive(Deserialize)]
+struct Record<'a> {
+    x: f64,
+    y: f64,
+    #[serde(borrow)]
+    date: Cow<'a, str>,
+    driver_number: u32,
+}
+
+impl Record<'_> {
+    fn decode(&self) -> Result<LocationData, String> {
+        Ok(LocationData {
+            x: self.x,
+            y: self.y,
+            date: parse_date(&self.date)?,
+            driver_number: self.driver_number,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const RECORD: &str = r#"{"x": 1, "y": -2.5, "date": "2023-08-27T13:00:00.25+00:00", "driver_number": 1}"#;
+
+    // Feeds `chunks` one after another and returns the samples and skipped records
+    fn parse(chunks: &[&[u8]]) -> Result<(Vec<LocationData>, SkippedRecords), Box<dyn StdError>> {
+        let mut parser = LocationParser::new();
+        let mut samples = Vec::new();
+        for chunk in chunks {
+            parser.push(chunk, |sample| samples.push(sample))?;
+        }
+        Ok((samples, parser.finish()?))
+    }
+
+    fn sample(x: f64, y: f64, driver_number: u32) -> LocationData {
+        LocationData {
+            x,
+            y,
+            date: timestamp::parse_utc("2023-08-27T13:00:00.25Z").unwrap(),
+            driver_number,
+        }
+    }
+
+    #[test]
+    fn records_split_at_every_byte() {
+        let unusual = r#"{"meeting": {"key": [1, 2]}, "x": 3, "y": 4, "date": "2023-08-27T13:00:00.25Z", "driver_number": 44}"#;
+        let body = format!(" [\n{},{} , {}]\n", RECORD, unusual, RECORD.replace("\"x\": 1", "\"x\": 5"));
+        let body = body.as_bytes();
+        let expected = vec![sample(1.0, -2.5, 1), sample(3.0, 4.0, 44), sample(5.0, -2.5, 1)];
+
+        assert_eq!(parse(&[body]).unwrap(), (expected.clone(), SkippedRecords::default()));
+        for split in 0..=body.len() {
+            let (samples, skipped) = parse(&[&body[..split], &body[split..]]).unwrap();
+            assert_eq!(samples, expected, "split at {}", split);
+            assert_eq!(skipped.count, 0);
+        }
+        let bytes: Vec<&[u8]> = body.chunks(1).collect();
+        assert_eq!(parse(&bytes).unwrap().0, expected);
+    }
+
+    #[test]
+    fn escaped_and_nested_records_go_through_serde() {
+        let body = r#"[{"note": "a \"quoted\" } ]", "x": 1, "y": -2.5, "session": {"laps": [1, [2]]},
+            "date": "2023-08-27T13:00:00.25Z", "driver_number": 1}]"#;
+        let (samples, skipped) = parse(&[body.as_bytes()]).unwrap();
+        assert_eq!(samples, vec![sample(1.0, -2.5, 1)]);
+        assert_eq!(skipped.count, 0);
+    }
+
+    #[test]
+    fn unreadable_records_are_skipped() {
+        let body = format!(
+            r#"[{}, {{"x": 1, "y": 2}}, {{"x": "far", "y": 2, "date": "2023-08-27T13:00:00Z", "driver_number": 1}}, {}]"#,
+            RECORD, RECORD
+        );
+        let (samples, skipped) = parse(&[body.as_bytes()]).unwrap();
+        assert_eq!(samples.len(), 2);
+        assert_eq!(skipped.count, 2);
+        assert!(skipped.first_error.unwrap().contains("date"));
+    }
+
+    #[test]
+    fn truncated_responses_are_errors() {
+        for body in ["", "[", "[{\"x\": 1", &format!("[{}", RECORD), &format!("[{},", RECORD)] {
+            assert!(parse(&[body.as_bytes()]).is_err(), "{}", body);
+        }
+        assert_eq!(parse(&[b"[]"]).unwrap(), (Vec::new(), SkippedRecords::default()));
+    }
+
+    #[test]
+    fn trailing_garbage_is_an_error() {
+        for body in ["[] x", "[]]", "[][]", &format!("[{}]{}", RECORD, RECORD)] {
+            assert!(parse(&[body.as_bytes()]).is_err(), "{}", body);
+        }
+        assert!(parse(&[b"{}"]).is_err()); // Not an array
+    }
+
+    #[test]
+    fn missing_or_extra_commas_are_errors() {
+        for body in [
+            format!("[{} {}]", RECORD, RECORD),
+            format!("[{},,{}]", RECORD, RECORD),
+            format!("[,{}]", RECORD),
+            format!("[{},]", RECORD),
+        ] {
+            assert!(parse(&[body.as_bytes()]).is_err(), "{}", body);
+        }
+
+        // Within a record the fields are invalid JSON, so the record is skipped
+        for record in [RECORD.replace(", \"y\"", " \"y\""), RECORD.replace(", \"y\"", ",, \"y\""), RECORD.replace("{", "{,")] {
+            let (samples, skipped) = parse(&[format!("[{}]", record).as_bytes()]).unwrap();
+            assert!(samples.is_empty(), "{}", record);
+            assert_eq!(skipped.count, 1, "{}", record);
+        }
+    }
+}
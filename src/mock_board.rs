@@ -0,0 +1,268 @@
+use std::collections::VecDeque;
+use std::io;
+use std::net::{SocketAddr, UdpSocket};
+use std::path::Path;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Condvar, Mutex};
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant};
+
+use crate::driver_info::Rgb;
+use crate::led_protocol::{decode_frame, FrameDecoder, HEADER_LEN};
+
+// How often the listener threads check whether the board was dropped
+const POLL_INTERVAL: Duration = Duration::from_millis(100);
+// Frames kept for `frames` and `wait_for_frames`, older ones are dropped so a
+// board left running doesn't grow without bound
+pub const HISTORY_FRAMES: usize = 10_000;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FrameSource {
+    Serial,
+    Udp,
+    Recording,
+}
+
+#[derive(Debug, Clone)]
+pub struct ReceivedFrame {
+    pub index: usize, // Frames received before this one
+    pub source: FrameSource,
+    pub received: Instant,
+    pub colors: Vec<Rgb>, // Index 0 is LED 1
+}
+
+#[derive(Default)]
+struct Received {
+    frames: VecDeque<ReceivedFrame>, // The last `HISTORY_FRAMES`
+    count: usize, // Every frame since the board started
+    errors: usize,
+}
+
+// Software stand-in for the LED board: listens on a serial pseudo-terminal
+// and/or a UDP socket and records every frame it decodes
+pub struct MockBoard {
+    received: Arc<(Mutex<Received>, Condvar)>,
+    stop: Arc<AtomicBool>,
+    threads: Vec<JoinHandle<()>>,
+    #[cfg(unix)]
+    _serial_slave: Option<serialport::TTYPort>, // Kept open so the pty stays up between writers
+}
+
+impl MockBoard {
+    pub fn new() -> MockBoard {
+        MockBoard {
+            received: Arc::new((Mutex::new(Received::default()), Condvar::new())),
+            stop: Arc::new(AtomicBool::new(false)),
+            threads: Vec::new(),
+            #[cfg(unix)]
+            _serial_slave: None,
+        }
+    }
+
+    // Listens for datagrams on `addr`; port 0 picks a free one, the bound
+    // address is returned
+    pub fn listen_udp(&mut self, addr: &str) -> io::Result<SocketAddr> {
+        let socket = UdpSocket::bind(addr)?;
+        socket.set_read_timeout(Some(POLL_INTERVAL))?;
+        let local = socket.local_addr()?;
+
+        let received = self.received.clone();
+        let stop = self.stop.clone();
+        self.threads.push(thread::spawn(move || {
+            let mut datagram = vec![0u8; 65536];
+            while !stop.load(Ordering::Relaxed) {
+                match socket.recv(&mut datagram) {
+                    Ok(length) => match decode_frame(&datagram[..length]) {
+                        Some(colors) => record(&received, FrameSource::Udp, vec![colors], 0),
+                        None => record(&received, FrameSource::Udp, Vec::new(), 1),
+                    },
+                    Err(e) if matches!(e.kind(), io::ErrorKind::WouldBlock | io::ErrorKind::TimedOut) => {}
+                    Err(e) => {
+                        eprintln!("Mock board UDP receive failed: {}", e);
+                        break;
+                    }
+                }
+            }
+        }));
+        Ok(local)
+    }
+
+    // Creates a pseudo-terminal and returns the path for `SerialOutput::open`
+    #[cfg(unix)]
+    pub fn open_serial(&mut self) -> Result<String, serialport::Error> {
+        use serialport::SerialPort;
+        use std::io::Read;
+
+        let (mut master, slave) = serialport::TTYPort::pair()?;
+        let path = slave
+            .name()
+            .ok_or_else(|| serialport::Error::new(serialport::ErrorKind::NoDevice, "pty has no name"))?;
+        master.set_timeout(POLL_INTERVAL)?;
+        self._serial_slave = Some(slave);
+
+        let received = self.received.clone();
+        let stop = self.stop.clone();
+        self.threads.push(thread::spawn(move || {
+            let mut decoder = FrameDecoder::new();
+            let mut chunk = [0u8; 4096];
+            while !stop.load(Ordering::Relaxed) {
+                match master.read(&mut chunk) {
+                    Ok(length) => {
+                        let errors = decoder.errors();
+                        let frames = decoder.push(&chunk[..length]);
+                        record(&received, FrameSource::Serial, frames, decoder.errors() - errors);
+                    }
+                    Err(e) if e.kind() == io::ErrorKind::TimedOut => {}
+                    // A writer closing its end hangs up the pty until the next one opens it
+                    Err(_) => thread::sleep(POLL_INTERVAL),
+                }
+            }
+        }));
+        Ok(path)
+    }
+
+    // Reads a file written by `RecorderOutput` as if its frames had been
+    // received, ignoring their timestamps; a malformed frame ends the read
+    pub fn read_recording(&mut self, path: &Path) -> io::Result<()> {
+        let bytes = std::fs::read(path)?;
+        let mut frames = Vec::new();
+        let mut rest = &bytes[..];
+        while !rest.is_empty() {
+            let frame = rest.get(4..).and_then(|frame| {
+                let count = u16::from_le_bytes([*frame.get(2)?, *frame.get(3)?]) as usize;
+                let length = (HEADER_LEN + count * 3 + 1).min(frame.len());
+                Some((decode_frame(&frame[..length])?, length))
+            });
+            let Some((colors, length)) = frame else {
+                record(&self.received, FrameSource::Recording, frames, 1);
+                return Ok(());
+            };
+            frames.push(colors);
+            rest = &rest[4 + length..];
+        }
+        record(&self.received, FrameSource::Recording, frames, 0);
+        Ok(())
+    }
+
+    // The last `HISTORY_FRAMES` frames, oldest first
+    pub fn frames(&self) -> Vec<ReceivedFrame> {
+        self.received.0.lock().unwrap().frames.iter().cloned().collect()
+    }
+
+    // Frames received since the board started, including ones no longer kept
+    pub fn frame_count(&self) -> usize {
+        self.received.0.lock().unwrap().count
+    }
+
+    pub fn latest(&self) -> Option<ReceivedFrame> {
+        self.received.0.lock().unwrap().frames.back().cloned()
+    }
+
+    // Malformed datagrams and recordings, and skipped serial garbage
+    pub fn decode_errors(&self) -> usize {
+        self.received.0.lock().unwrap().errors
+    }
+
+    // Blocks until at least `count` frames arrived in total or the timeout
+    // passed, then returns the frames still kept
+    pub fn wait_for_frames(&self, count: usize, timeout: Duration) -> Vec<ReceivedFrame> {
+        let (lock, arrived) = &*self.received;
+        let received = arrived
+            .wait_timeout_while(lock.lock().unwrap(), timeout, |received| received.count < count)
+            .unwrap()
+            .0;
+        received.frames.iter().cloned().collect()
+    }
+}
+
+impl Default for MockBoard {
+    fn default() -> MockBoard {
+        MockBoard::new()
+    }
+}
+
+impl Drop for MockBoard {
+    fn drop(&mut self) {
+        self.stop.store(true, Ordering::Relaxed);
+        for thread in self.threads.drain(..) {
+            let _ = thread.join();
+        }
+    }
+}
+
+fn record(received: &(Mutex<Received>, Condvar), source: FrameSource, frames: Vec<Vec<Rgb>>, errors: usize) {
+    let (lock, arrived) = received;
+    let mut received = lock.lock().unwrap();
+    received.errors += errors;
+    let now = Instant::now();
+    for colors in frames {
+        let index = received.count;
+        received.frames.push_back(ReceivedFrame { index, source, received: now, colors });
+        received.count += 1;
+    }
+    let excess = received.frames.len().saturating_sub(HISTORY_FRAMES);
+    received.frames.drain(..excess);
+    arrived.notify_all();
+}
+
+// Window showing what the mock board last received, laid out like the real board
+#[cfg(feature = "gui")]
+pub struct MockBoardApp {
+    board: MockBoard,
+    layout: crate::layout::TrackLayout,
+}
+
+#[cfg(feature = "gui")]
+impl MockBoardApp {
+    pub fn new(board: MockBoard, layout: crate::layout::TrackLayout) -> MockBoardApp {
+        MockBoardApp { board, layout }
+    }
+}
+
+#[cfg(feature = "gui")]
+impl eframe::App for MockBoardApp {
+    fn update(&mut self, ctx: &eframe::egui::Context, _frame: &mut eframe::Frame) {
+        use crate::gui::{to_color32, TrackTransform, ViewState};
+        use eframe::egui;
+
+        let latest = self.board.latest();
+        egui::TopBottomPanel::top("mock_board_status").show(ctx, |ui| {
+            ui.horizontal(|ui| {
+                ui.label(format!("{} frames", self.board.frame_count()));
+                ui.separator();
+                ui.label(format!("{} decode errors", self.board.decode_errors()));
+                if let Some(frame) = &latest {
+                    ui.separator();
+                    ui.label(format!(
+                        "last: {} LEDs via {:?}, {:.1} s ago",
+                        frame.colors.len(),
+                        frame.source,
+                        frame.received.elapsed().as_secs_f32()
+                    ));
+                }
+            });
+        });
+
+        egui::CentralPanel::default().show(ctx, |ui| {
+            let (response, painter) = ui.allocate_painter(ui.available_size(), egui::Sense::hover());
+            let transform = TrackTransform::new(&self.layout, response.rect, &ViewState::default());
+            let size = egui::vec2(transform.led_size, transform.led_size);
+            let colors = latest.as_ref().map_or(&[][..], |frame| &frame.colors[..]);
+            for coord in &self.layout.leds {
+                let color = coord
+                    .led_number
+                    .checked_sub(1)
+                    .and_then(|index| colors.get(index))
+                    .map_or(egui::Color32::BLACK, |&color| to_color32(color));
+                painter.rect_filled(
+                    egui::Rect::from_center_size(transform.to_screen(coord), size),
+                    egui::Rounding::same(0.0),
+                    color,
+                );
+            }
+        });
+
+        // Frames arrive on other threads, poll for them
+        ctx.request_repaint_after(std::time::Duration::from_millis(30));
+    }
+}
@@ -0,0 +1,275 @@
+use chrono::{DateTime, Duration, SecondsFormat, Utc};
+use reqwest::Client;
+use serde::Deserialize;
+use std::collections::BTreeMap;
+use std::error::Error as StdError;
+
+use crate::frames::LocationData;
+use crate::location_parser::{LocationParser, SkippedRecords};
+use crate::pit::PitStop;
+use crate::samples::LocationSamples;
+use crate::session::SessionMetadata;
+use crate::timestamp;
+
+pub const SESSION_KEY: &str = "9149";
+// 2023 grid, used when the session roster cannot be fetched
+pub const DEFAULT_DRIVER_NUMBERS: [u32; 20] = [
+    1, 2, 4, 10, 11, 14, 16, 18, 20, 22, 23, 24, 27, 31, 40, 44, 55, 63, 77, 81,
+];
+
+#[derive(Debug, Deserialize)]
+struct SessionDriver {
+    driver_number: u32,
+}
+
+// Everyone entered in the session, reserve drivers included
+pub async fn fetch_driver_numbers(session_key: &str) -> Result<Vec<u32>, Box<dyn StdError>> {
+    let url = format!("https://api.openf1.org/v1/drivers?session_key={}", session_key);
+    let resp = Client::new().get(&url).send().await?;
+    if !resp.status().is_success() {
+        eprintln!("Failed to fetch drivers: HTTP {}", resp.status());
+        return Ok(DEFAULT_DRIVER_NUMBERS.to_vec());
+    }
+
+    let drivers: Vec<SessionDriver> = resp.json().await?;
+    let mut driver_numbers: Vec<u32> = drivers.iter().map(|d| d.driver_number).collect();
+    driver_numbers.sort_unstable();
+    driver_numbers.dedup();
+    if driver_numbers.is_empty() {
+        return Ok(DEFAULT_DRIVER_NUMBERS.to_vec());
+    }
+    Ok(driver_numbers)
+}
+
+// Samples of every driver in time order, stored compactly as they arrive,
+// and the records skipped for each driver with unreadable ones
+pub async fn fetch_data(
+    driver_numbers: &[u32],
+) -> Result<(LocationSamples, BTreeMap<u32, SkippedRecords>), Box<dyn StdError>> {
+    let session_key = SESSION_KEY;
+    let start_time: &str = "2023-08-27T12:58:56.200";
+    let end_time: &str = "2023-08-27T13:20:54.300";
+
+    let client = Client::new();
+    let mut all_data = LocationSamples::new();
+    let mut skipped = BTreeMap::new();
+
+    for &driver_number in driver_numbers {
+        // The store refusing a sample fails the fetch once the response is read
+        let mut stored = Ok(());
+        let driver_skipped =
+            fetch_driver_locations(&client, session_key, driver_number, start_time, end_time, |sample| {
+                if stored.is_ok() {
+                    stored = all_data.push(&sample);
+                }
+            })
+            .await?;
+        stored?;
+        if driver_skipped.count > 0 {
+            skipped.insert(driver_number, driver_skipped);
+        }
+    }
+
+    // Sort the data by the date field
+    all_data.sort_by_date();
+    all_data.shrink_to_fit();
+    Ok((all_data, skipped))
+}
+
+// Location samples of one driver between two timestamps, without (0, 0)
+// positions. The response is parsed as it downloads and each sample handed to
+// `sample`, so it is never held whole. Records that cannot be read are
+// reported and left out.
+pub async fn fetch_driver_locations(
+    client: &Client,
+    session_key: &str,
+    driver_number: u32,
+    start_time: &str,
+    end_time: &str,
+    sample: impl FnMut(LocationData),
+) -> Result<SkippedRecords, Box<dyn StdError>> {
+    let url = format!(
+        "https://api.openf1.org/v1/location?session_key={}&driver_number={}&date>{}&date<{}",
+        session_key, driver_number, start_time, end_time,
+    );
+    fetch_locations(client, &url, driver_number, sample).await
+}
+
+async fn fetch_locations(
+    client: &Client,
+    url: &str,
+    driver_number: u32,
+    mut sample: impl FnMut(LocationData),
+) -> Result<SkippedRecords, Box<dyn StdError>> {
+    #[allow(unused_mut)] // Only read in chunks natively
+    let mut resp = client.get(url).send().await?;
+    if !resp.status().is_success() {
+        eprintln!(
+            "Failed to fetch data for driver {}: HTTP {}",
+            driver_number,
+            resp.status()
+        );
+        return Ok(SkippedRecords::default());
+    }
+
+    let mut parser = LocationParser::new();
+    let mut on_sample = |data: LocationData| {
+        if data.x != 0.0 && data.y != 0.0 {
+            sample(data);
+        }
+    };
+    // Browsers only hand over the whole body
+    #[cfg(not(target_arch = "wasm32"))]
+    while let Some(chunk) = resp.chunk().await? {
+        parser.push(&chunk, &mut on_sample)?;
+    }
+    #[cfg(target_arch = "wasm32")]
+    parser.push(&resp.bytes().await?, &mut on_sample)?;
+
+    let skipped = parser.finish()?;
+    if let Some(error) = &skipped.first_error {
+        eprintln!(
+            "Skipped {} unreadable location records of driver {}, the first: {}",
+            skipped.count, driver_number, error
+        );
+    }
+    Ok(skipped)
+}
+
+#[derive(Debug, Deserialize)]
+struct SessionResponse {
+    meeting_key: Option<u32>,
+    circuit_short_name: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+struct MeetingResponse {
+    meeting_name: Option<String>,
+}
+
+// Circuit and meeting name of a session; missing values are left empty
+pub async fn fetch_session_metadata(session_key: &str) -> Result<SessionMetadata, Box<dyn StdError>> {
+    let client = Client::new();
+    let mut metadata = SessionMetadata {
+        session_key: session_key.to_string(),
+        ..SessionMetadata::default()
+    };
+
+    let url = format!("https://api.openf1.org/v1/sessions?session_key={}", session_key);
+    let sessions: Vec<SessionResponse> = client.get(&url).send().await?.json().await?;
+    let Some(session) = sessions.into_iter().next() else {
+        return Ok(metadata);
+    };
+    metadata.circuit = session.circuit_short_name.unwrap_or_default();
+
+    if let Some(meeting_key) = session.meeting_key {
+        let url = format!("https://api.openf1.org/v1/meetings?meeting_key={}", meeting_key);
+        let meetings: Vec<MeetingResponse> = client.get(&url).send().await?.json().await?;
+        if let Some(meeting_name) = meetings.into_iter().next().and_then(|m| m.meeting_name) {
+            metadata.meeting_name = meeting_name;
+        }
+    }
+
+    Ok(metadata)
+}
+
+pub async fn fetch_pit_stops(session_key: &str) -> Result<Vec<PitStop>, Box<dyn StdError>> {
+    let url = format!("https://api.openf1.org/v1/pit?session_key={}", session_key);
+    let resp = Client::new().get(&url).send().await?;
+    if !resp.status().is_success() {
+        eprintln!("Failed to fetch pit stops: HTTP {}", resp.status());
+        return Ok(Vec::new());
+    }
+
+    let mut pit_stops: Vec<PitStop> = resp.json().await?;
+    pit_stops.sort_by_key(|stop| stop.date);
+    Ok(pit_stops)
+}
+
+// Subset of the OpenF1 `laps` endpoint needed to find one lap's time window
+#[derive(Debug, Deserialize)]
+struct LapInfo {
+    date_start: Option<String>,
+    lap_duration: Option<f64>,
+}
+
+// Location samples of a single lap, in time order
+pub async fn fetch_reference_lap(
+    session_key: &str,
+    driver_number: u32,
+    lap_number: u32,
+) -> Result<Vec<LocationData>, Box<dyn StdError>> {
+    let client = Client::new();
+    let url = format!(
+        "https://api.openf1.org/v1/laps?session_key={}&driver_number={}&lap_number={}",
+        session_key, driver_number, lap_number,
+    );
+    let laps: Vec<LapInfo> = client.get(&url).send().await?.json().await?;
+    let lap = laps
+        .first()
+        .ok_or_else(|| format!("No lap {} for driver {}", lap_number, driver_number))?;
+
+    let (Some(date_start), Some(lap_duration)) = (&lap.date_start, lap.lap_duration) else {
+        return Err(format!("Lap {} of driver {} has no timing data", lap_number, driver_number).into());
+    };
+    let start: DateTime<Utc> = timestamp::parse_utc(date_start)?;
+    let end = start + Duration::milliseconds((lap_duration * 1000.0) as i64);
+
+    let mut samples = Vec::new();
+    fetch_driver_locations(
+        &client,
+        session_key,
+        driver_number,
+        &start.to_rfc3339_opts(SecondsFormat::Millis, true),
+        &end.to_rfc3339_opts(SecondsFormat::Millis, true),
+        |sample| samples.push(sample),
+    )
+    .await?;
+    samples.sort_by_key(|d| d.date);
+    Ok(samples)
+}
+
+#[cfg(all(test, not(target_arch = "wasm32")))]
+mod tests {
+    use super::*;
+    use std::io::{Read, Write};
+    use std::net::TcpListener;
+    use std::thread;
+
+    // Serves one response with a chunked body, a few bytes per chunk
+    fn serve_chunked(body: &'static str) -> String {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        thread::spawn(move || {
+            let (mut stream, _) = listener.accept().unwrap();
+            let mut request = [0u8; 4096];
+            let _ = stream.read(&mut request).unwrap();
+            let mut response = String::from("HTTP/1.1 200 OK\r\ncontent-type: application/json\r\ntransfer-encoding: chunked\r\n\r\n");
+            for chunk in body.as_bytes().chunks(7) {
+                response.push_str(&format!("{:x}\r\n{}\r\n", chunk.len(), std::str::from_utf8(chunk).unwrap()));
+            }
+            response.push_str("0\r\n\r\n");
+            stream.write_all(response.as_bytes()).unwrap();
+        });
+        format!("http://{}/v1/location", addr)
+    }
+
+    #[tokio::test]
+    async fn locations_are_read_from_a_chunked_response() {
+        let url = serve_chunked(
+            r#"[{"x":10,"y":20,"z":0,"driver_number":44,"date":"2023-08-27T13:00:00.25+00:00","session_key":9149},
+                {"x":0,"y":0,"z":0,"driver_number":44,"date":"2023-08-27T13:00:00.5+00:00","session_key":9149},
+                {"x":11,"y":21,"z":0,"driver_number":44,"date":"not a date","session_key":9149},
+                {"x":12,"y":22,"z":0,"driver_number":44,"date":"2023-08-27T13:00:01","session_key":9149}]"#,
+        );
+        let mut samples = Vec::new();
+        let skipped = fetch_locations(&Client::new(), &url, 44, |sample| samples.push(sample)).await.unwrap();
+
+        assert_eq!(skipped.count, 1);
+        // (0, 0) positions are left out
+        assert_eq!(samples.len(), 2);
+        assert_eq!((samples[0].x, samples[0].y, samples[0].driver_number), (10.0, 20.0, 44));
+        assert_eq!(samples[0].date, timestamp::parse_utc("2023-08-27T13:00:00.250Z").unwrap());
+        assert_eq!(samples[1].date, timestamp::parse_utc("2023-08-27T13:00:01Z").unwrap());
+    }
+}
@@ -0,0 +1,97 @@
+use chrono::{DateTime, Duration, Utc};
+use serde::Deserialize;
+use std::collections::HashMap;
+
+// Time in the pit lane assumed for stops OpenF1 has no duration for: about a
+// drive-through plus a tyre change on most circuits
+pub const DEFAULT_PIT_LANE_SECONDS: f64 = 22.0;
+
+// One entry of the OpenF1 `pit` endpoint. `date` is pit lane entry and
+// `pit_duration` the time spent in the pit lane, in seconds.
+#[derive(Debug, Clone, Deserialize)]
+pub struct PitStop {
+    #[serde(deserialize_with = "crate::frames::deserialize_datetime")]
+    pub date: DateTime<Utc>,
+    pub driver_number: u32,
+    pub pit_duration: Option<f64>,
+}
+
+impl PitStop {
+    // Seconds in the pit lane, `DEFAULT_PIT_LANE_SECONDS` when unknown
+    pub fn duration(&self) -> f64 {
+        self.pit_duration.unwrap_or(DEFAULT_PIT_LANE_SECONDS)
+    }
+
+    pub fn exit_time(&self) -> DateTime<Utc> {
+        self.date + Duration::milliseconds((self.duration() * 1000.0) as i64)
+    }
+}
+
+// Pit stops by driver, each driver's sorted by pit lane entry, for looking
+// up every sample of a session
+#[derive(Debug, Default)]
+pub struct PitStopIndex<'a> {
+    by_driver: HashMap<u32, Vec<&'a PitStop>>,
+}
+
+impl<'a> PitStopIndex<'a> {
+    pub fn new(pit_stops: &'a [PitStop]) -> PitStopIndex<'a> {
+        let mut by_driver: HashMap<u32, Vec<&PitStop>> = HashMap::new();
+        for stop in pit_stops {
+            by_driver.entry(stop.driver_number).or_default().push(stop);
+        }
+        for stops in by_driver.values_mut() {
+            stops.sort_by_key(|stop| stop.date);
+        }
+        PitStopIndex { by_driver }
+    }
+
+    // Pit stop the driver is in at the given time, if any
+    pub fn stop_at(&self, driver_number: u32, date: DateTime<Utc>) -> Option<&'a PitStop> {
+        let stops = self.by_driver.get(&driver_number)?;
+        // The last stop entered by then
+        let entered = stops.partition_point(|stop| stop.date <= date);
+        let stop = *stops.get(entered.checked_sub(1)?)?;
+        (date <= stop.exit_time()).then_some(stop)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn stop(driver_number: u32, seconds: i64, pit_duration: Option<f64>) -> PitStop {
+        PitStop {
+            date: time(seconds),
+            driver_number,
+            pit_duration,
+        }
+    }
+
+    fn time(seconds: i64) -> DateTime<Utc> {
+        DateTime::from_timestamp(1_693_141_200 + seconds, 0).unwrap()
+    }
+
+    #[test]
+    fn stops_are_found_per_driver() {
+        let stops = [stop(1, 1000, Some(21.5)), stop(44, 900, Some(25.0)), stop(1, 100, Some(20.0))];
+        let index = PitStopIndex::new(&stops);
+
+        assert_eq!(index.stop_at(1, time(110)).unwrap().date, time(100));
+        assert_eq!(index.stop_at(1, time(1021)).unwrap().date, time(1000));
+        assert!(index.stop_at(1, time(1022)).is_none());
+        assert!(index.stop_at(1, time(99)).is_none());
+        assert!(index.stop_at(1, time(500)).is_none());
+        assert!(index.stop_at(44, time(110)).is_none());
+        assert!(index.stop_at(16, time(110)).is_none());
+    }
+
+    #[test]
+    fn stops_without_a_duration_last_the_default() {
+        let stops = [stop(1, 100, None)];
+        let index = PitStopIndex::new(&stops);
+        assert!(index.stop_at(1, time(100 + DEFAULT_PIT_LANE_SECONDS as i64 - 1)).is_some());
+        assert!(index.stop_at(1, time(100 + DEFAULT_PIT_LANE_SECONDS as i64 + 1)).is_none());
+        assert_eq!(stops[0].duration(), DEFAULT_PIT_LANE_SECONDS);
+    }
+}
@@ -0,0 +1,473 @@
+use std::collections::BTreeSet;
+use std::error::Error as StdError;
+use std::sync::mpsc::{self, Receiver, Sender};
+use std::sync::{Arc, Condvar, Mutex, MutexGuard};
+use std::time::Duration;
+use web_time::Instant;
+
+use crate::driver_info::{DriverInfo, Rgb};
+use crate::frame_store::FrameStore;
+use crate::frames::UpdateFrame;
+use crate::hardware::{frame_colors, ColorCorrection};
+use crate::layout::TrackLayout;
+use crate::sinks::{OutputSinks, SinkOptions, SinkStats};
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum PlaybackEvent {
+    // The frame on screen changed; `index` frames have started (the current
+    // frame is `index - 1`)
+    Frame { index: usize, race_time: f64 },
+    // The last frame is showing and the clock stopped
+    Finished,
+}
+
+// How frames turn into output colours, changed by the GUI while playing
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct OutputStyle {
+    pub driver_info: Vec<DriverInfo>,
+    pub hidden_drivers: BTreeSet<u32>, // Left off the outputs
+    pub correction: ColorCorrection,
+}
+
+impl OutputStyle {
+    pub fn colors(&self, frame: &UpdateFrame, layout: &TrackLayout) -> Vec<Rgb> {
+        let colors = if self.hidden_drivers.is_empty() {
+            frame_colors(frame, layout, &self.driver_info)
+        } else {
+            let visible = UpdateFrame {
+                drivers: frame
+                    .drivers
+                    .iter()
+                    .filter(|d| !self.hidden_drivers.contains(&d.driver_number))
+                    .copied()
+                    .collect(),
+                ..frame.clone()
+            };
+            frame_colors(&visible, layout, &self.driver_info)
+        };
+        self.correction.apply(&colors)
+    }
+}
+
+// Race time running `speed` times faster than the wall clock; stopped it
+// holds its time
+#[derive(Debug, Clone, Copy)]
+struct Clock {
+    started: Option<Instant>,
+    base: f64, // Race time when `started`
+    speed: f64,
+}
+
+impl Clock {
+    fn now(&self) -> f64 {
+        self.base + self.started.map_or(0.0, |started| started.elapsed().as_secs_f64() * self.speed)
+    }
+
+    fn stop(&mut self) {
+        self.base = self.now();
+        self.started = None;
+    }
+
+    fn set_speed(&mut self, speed: f64) {
+        if self.started.is_some() {
+            self.base = self.now();
+            self.started = Some(Instant::now());
+        }
+        self.speed = speed;
+    }
+}
+
+struct State {
+    frames: Arc<FrameStore>,
+    layout: TrackLayout,
+    frame_duration: f64, // Seconds
+    clock: Clock,
+    playing: bool, // Between `start` and `reset`, also once finished
+    finished: bool,
+    index: usize, // Frames started on screen
+    display_latency: Duration,
+    outputs: OutputSinks,
+    style: OutputStyle,
+    listeners: Vec<Sender<PlaybackEvent>>,
+    waker: Option<Box<dyn Fn() + Send>>,
+    closed: bool,
+}
+
+impl State {
+    // Advances the screen and the outputs to the clock, returns the wall time
+    // until the next frame is due anywhere
+    fn step(&mut self) -> Option<Duration> {
+        if !self.playing {
+            return None;
+        }
+        let race_time = self.clock.now();
+        let speed = self.clock.speed;
+        let (count, frame_duration) = (self.frames.len(), self.frame_duration);
+
+        let index = frame_index(race_time + self.display_latency.as_secs_f64() * speed, count, frame_duration);
+        if index != self.index {
+            self.index = index;
+            self.emit(PlaybackEvent::Frame { index, race_time });
+        }
+
+        let (frames, layout, style) = (&self.frames, &self.layout, &self.style);
+        self.outputs.update(race_time, speed, |time| {
+            let index = frame_index(time, count, frame_duration);
+            let frame = frames.frame(index.checked_sub(1)?)?;
+            Some((index, style.colors(&frame, layout)))
+        });
+
+        if self.finished {
+            return None;
+        }
+        // Done once the screen and every output have reached the last frame;
+        // an output with less latency than the screen gets there later
+        if index == count && (count == 0 || self.outputs.reached(count)) {
+            self.finished = true;
+            self.clock.stop();
+            self.emit(PlaybackEvent::Finished);
+            return None;
+        }
+
+        // Next frame boundary of the screen or of any output, whichever is first
+        std::iter::once(self.display_latency)
+            .chain(self.outputs.iter().map(|sink| sink.options().latency))
+            .map(|latency| {
+                let time = race_time + latency.as_secs_f64() * speed;
+                let next = ((time / frame_duration).floor() + 1.0) * frame_duration;
+                // `speed` is always positive, but never panic or spin on a bad one
+                Duration::try_from_secs_f64(((next - time) / speed).max(0.0)).unwrap_or(Duration::MAX)
+            })
+            .min()
+    }
+
+    fn emit(&mut self, event: PlaybackEvent) {
+        self.listeners.retain(|listener| listener.send(event).is_ok());
+        if let Some(waker) = &self.waker {
+            waker();
+        }
+    }
+}
+
+// Playback speeds must be finite and above zero: the clock never runs backwards
+pub fn check_speed(speed: f64) -> Result<f64, Box<dyn StdError>> {
+    if speed.is_finite() && speed > 0.0 {
+        Ok(speed)
+    } else {
+        Err(format!("Playback speed must be a positive number, got {}", speed).into())
+    }
+}
+
+// Number of frames started by `race_time`; the current frame is the last of them
+fn frame_index(race_time: f64, count: usize, frame_duration: f64) -> usize {
+    if race_time < 0.0 {
+        return 0;
+    }
+    ((race_time / frame_duration).floor() as usize).saturating_add(1).min(count)
+}
+
+// Plays frames on its own clock, independent of any window: feeds the
+// outputs and reports frame changes to subscribers. Natively a thread wakes
+// at every frame boundary; on wasm there are no threads and the owner calls
+// `poll` instead.
+pub struct Playback {
+    shared: Arc<(Mutex<State>, Condvar)>,
+    #[cfg(not(target_arch = "wasm32"))]
+    worker: Option<std::thread::JoinHandle<()>>,
+}
+
+impl Playback {
+    pub fn new(update_rate_ms: u64, frames: Arc<FrameStore>, layout: TrackLayout) -> Playback {
+        let state = State {
+            frames,
+            layout,
+            frame_duration: update_rate_ms.max(1) as f64 / 1000.0,
+            clock: Clock {
+                started: None,
+                base: 0.0,
+                speed: 1.0,
+            },
+            playing: false,
+            finished: false,
+            index: 0,
+            display_latency: Duration::ZERO,
+            outputs: OutputSinks::new(),
+            style: OutputStyle::default(),
+            listeners: Vec::new(),
+            waker: None,
+            closed: false,
+        };
+        let shared = Arc::new((Mutex::new(state), Condvar::new()));
+
+        #[cfg(not(target_arch = "wasm32"))]
+        let worker = {
+            let shared = shared.clone();
+            Some(std::thread::spawn(move || {
+                let (lock, changed) = &*shared;
+                let mut state = lock.lock().unwrap();
+                while !state.closed {
+                    state = match state.step() {
+                        Some(wait) => changed.wait_timeout(state, wait).unwrap().0,
+                        None => changed.wait(state).unwrap(),
+                    };
+                }
+            }))
+        };
+
+        Playback {
+            shared,
+            #[cfg(not(target_arch = "wasm32"))]
+            worker,
+        }
+    }
+
+    fn state(&self) -> MutexGuard<'_, State> {
+        self.shared.0.lock().unwrap()
+    }
+
+    // Runs `change` and wakes the playback thread to act on it
+    fn update<T>(&self, change: impl FnOnce(&mut State) -> T) -> T {
+        let result = change(&mut self.state());
+        self.shared.1.notify_all();
+        result
+    }
+
+    pub fn set_outputs(&self, outputs: OutputSinks) {
+        self.update(|state| state.outputs = outputs);
+    }
+
+    // The screen is shown frames ahead of the clock by its latency, like the outputs
+    pub fn set_display_latency(&self, latency: Duration) {
+        self.update(|state| state.display_latency = latency);
+    }
+
+    pub fn set_style(&self, style: OutputStyle) {
+        if self.state().style != style {
+            self.update(|state| state.style = style);
+        }
+    }
+
+    pub fn set_speed(&self, speed: f64) -> Result<(), Box<dyn StdError>> {
+        let speed = check_speed(speed)?;
+        if self.state().clock.speed != speed {
+            self.update(|state| state.clock.set_speed(speed));
+        }
+        Ok(())
+    }
+
+    // Called on every frame event, e.g. to repaint a window
+    pub fn set_waker(&self, waker: impl Fn() + Send + 'static) {
+        self.state().waker = Some(Box::new(waker));
+    }
+
+    pub fn subscribe(&self) -> Receiver<PlaybackEvent> {
+        let (sender, receiver) = mpsc::channel();
+        self.state().listeners.push(sender);
+        receiver
+    }
+
+    // Plays from the first frame
+    pub fn start(&self) {
+        self.update(|state| {
+            state.clock.base = 0.0;
+            state.clock.started = Some(Instant::now());
+            state.playing = true;
+            state.finished = false;
+            state.index = 0;
+        });
+    }
+
+    // Stops, rewinds and turns the outputs off
+    pub fn reset(&self) {
+        self.update(|state| {
+            state.clock = Clock {
+                started: None,
+                base: 0.0,
+                ..state.clock
+            };
+            state.playing = false;
+            state.finished = false;
+            state.index = 0;
+            let dark = state.style.colors(&UpdateFrame::default(), &state.layout);
+            state.outputs.send_all(&dark);
+        });
+    }
+
+    // Steps playback on the caller's thread; returns the wall time until the
+    // next frame is due, `None` when nothing is playing
+    pub fn poll(&self) -> Option<Duration> {
+        self.state().step()
+    }
+
+    pub fn race_time(&self) -> f64 {
+        self.state().clock.now()
+    }
+
+    // Race time of the frame on screen
+    pub fn display_time(&self) -> f64 {
+        let state = self.state();
+        state.clock.now() + state.display_latency.as_secs_f64() * state.clock.speed
+    }
+
+    // Frames started on screen, as in `PlaybackEvent::Frame`
+    pub fn index(&self) -> usize {
+        self.state().index
+    }
+
+    pub fn is_playing(&self) -> bool {
+        self.state().playing
+    }
+
+    pub fn is_finished(&self) -> bool {
+        self.state().finished
+    }
+
+    pub fn has_outputs(&self) -> bool {
+        !self.state().outputs.is_empty()
+    }
+
+    pub fn output_stats(&self) -> Vec<(String, SinkOptions, SinkStats)> {
+        self.state()
+            .outputs
+            .iter()
+            .map(|sink| (sink.name().to_string(), sink.options(), sink.stats()))
+            .collect()
+    }
+}
+
+impl Drop for Playback {
+    fn drop(&mut self) {
+        self.update(|state| state.closed = true);
+        #[cfg(not(target_arch = "wasm32"))]
+        if let Some(worker) = self.worker.take() {
+            let _ = worker.join();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::frames::DriverData;
+    use crate::hardware::LedOutput;
+    use crate::led_coords::LedCoordinate;
+    use crate::sinks::DropPolicy;
+    use std::io;
+
+    // Frame `i` lights LED `i + 1`
+    fn playback(frame_count: usize) -> Playback {
+        let leds = (0..frame_count)
+            .map(|i| LedCoordinate { x_led: i as f64, y_led: 0.0, led_number: i + 1 })
+            .collect();
+        let frames = (0..frame_count).map(|i| UpdateFrame {
+            drivers: vec![DriverData { driver_number: 1, led_num: i + 1, pit_duration: None }],
+            ..UpdateFrame::default()
+        });
+        let layout = TrackLayout::from_coordinates("test", "Test", leds);
+        Playback::new(10, Arc::new(FrameStore::from_frames(frames).unwrap()), layout)
+    }
+
+    // Keeps the lit LED of every frame sent, `None` for a dark frame
+    struct Recorder(Arc<Mutex<Vec<Option<usize>>>>);
+
+    impl LedOutput for Recorder {
+        fn send(&mut self, colors: &[Rgb]) -> io::Result<()> {
+            self.0.lock().unwrap().push(colors.iter().position(|&c| c == Rgb::WHITE));
+            Ok(())
+        }
+    }
+
+    fn add_recorder(playback: &Playback, latency: Duration) -> Arc<Mutex<Vec<Option<usize>>>> {
+        let sent = Arc::new(Mutex::new(Vec::new()));
+        let mut outputs = OutputSinks::new();
+        let options = SinkOptions { latency, policy: DropPolicy::Queue(1000) };
+        outputs.add("recorder", Box::new(Recorder(sent.clone())), options);
+        playback.set_outputs(outputs);
+        sent
+    }
+
+    fn wait_until_finished(events: &Receiver<PlaybackEvent>) -> Vec<usize> {
+        let mut indices = Vec::new();
+        loop {
+            match events.recv_timeout(Duration::from_secs(5)).unwrap() {
+                PlaybackEvent::Frame { index, .. } => indices.push(index),
+                PlaybackEvent::Finished => return indices,
+            }
+        }
+    }
+
+    #[test]
+    fn finishes_once_every_output_has_the_last_frame() {
+        let playback = playback(10);
+        // The screen runs ahead of the output, which still needs the last frames
+        playback.set_display_latency(Duration::from_millis(50));
+        let sent = add_recorder(&playback, Duration::ZERO);
+        let events = playback.subscribe();
+        playback.start();
+
+        let indices = wait_until_finished(&events);
+        assert!(indices.windows(2).all(|pair| pair[0] < pair[1]));
+        assert_eq!(indices.last(), Some(&10));
+        assert!(playback.is_finished());
+        assert!(playback.race_time() >= 0.09);
+
+        drop(playback); // Waits for the output to send what was queued
+        let sent = sent.lock().unwrap();
+        assert_eq!(sent.last(), Some(&Some(9)));
+        assert!(sent.windows(2).all(|pair| pair[0] < pair[1]));
+    }
+
+    #[test]
+    fn outputs_are_sent_frames_ahead_by_their_latency() {
+        let playback = playback(10);
+        let sent = add_recorder(&playback, Duration::from_millis(50));
+        let events = playback.subscribe();
+        playback.start();
+        wait_until_finished(&events);
+        drop(playback);
+
+        // At race time 0 the output is already due the frame at 50 ms, the sixth
+        let sent = sent.lock().unwrap();
+        assert!(sent[0] >= Some(5), "first frame sent was {:?}", sent[0]);
+        assert_eq!(sent.last(), Some(&Some(9)));
+    }
+
+    #[test]
+    fn speed_runs_the_clock_faster() {
+        let playback = playback(100); // One second of race time
+        playback.set_speed(10.0).unwrap();
+        playback.start();
+        std::thread::sleep(Duration::from_millis(30));
+        assert!(playback.race_time() >= 0.3);
+
+        // Changing speed keeps the race time reached so far
+        let before = playback.race_time();
+        playback.set_speed(1.0).unwrap();
+        assert!(playback.race_time() >= before);
+    }
+
+    #[test]
+    fn invalid_speeds_are_refused() {
+        let playback = playback(10);
+        for speed in [0.0, -1.0, f64::NAN, f64::INFINITY] {
+            assert!(playback.set_speed(speed).is_err(), "speed {}", speed);
+        }
+        assert_eq!(playback.state().clock.speed, 1.0);
+        assert!(check_speed(0.5).is_ok());
+    }
+
+    #[test]
+    fn reset_rewinds_and_turns_the_outputs_off() {
+        let playback = playback(100);
+        let sent = add_recorder(&playback, Duration::ZERO);
+        playback.start();
+        std::thread::sleep(Duration::from_millis(30));
+        playback.reset();
+
+        assert!(!playback.is_playing());
+        assert_eq!(playback.index(), 0);
+        assert_eq!(playback.race_time(), 0.0);
+        drop(playback);
+        assert_eq!(sent.lock().unwrap().last(), Some(&None));
+    }
+}
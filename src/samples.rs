@@ -0,0 +1,157 @@
+use chrono::{DateTime, Duration, Utc};
+use std::error::Error as StdError;
+
+use crate::frames::LocationData;
+
+// Location samples stored column by column: 13 bytes a sample instead of 32
+// for `LocationData`. Times are kept in milliseconds from the first sample
+// pushed and positions as f32, which is exact for OpenF1's integer track
+// units. Up to 256 different drivers.
+#[derive(Debug, Clone, Default)]
+pub struct LocationSamples {
+    origin: Option<DateTime<Utc>>,
+    times_ms: Vec<i32>, // ~24 days either side of `origin`
+    x: Vec<f32>,
+    y: Vec<f32>,
+    drivers: Vec<u8>, // Index into `driver_numbers`
+    driver_numbers: Vec<u32>,
+}
+
+impl LocationSamples {
+    pub fn new() -> LocationSamples {
+        LocationSamples::default()
+    }
+
+    // Fails, without storing the sample, for a 257th driver or a time more
+    // than ~24 days from the first sample
+    pub fn push(&mut self, sample: &LocationData) -> Result<(), Box<dyn StdError>> {
+        let origin = self.origin.unwrap_or(sample.date);
+        let millis = i32::try_from((sample.date - origin).num_milliseconds()).map_err(|_| {
+            format!("Sample at {} is too far from the first sample at {} to be stored", sample.date, origin)
+        })?;
+        let driver = match self.driver_numbers.iter().position(|&n| n == sample.driver_number) {
+            Some(index) => index as u8,
+            None => {
+                let index = u8::try_from(self.driver_numbers.len())
+                    .map_err(|_| format!("More than 256 drivers in one session, at driver {}", sample.driver_number))?;
+                self.driver_numbers.push(sample.driver_number);
+                index
+            }
+        };
+        self.origin = Some(origin);
+        self.times_ms.push(millis);
+        self.x.push(sample.x as f32);
+        self.y.push(sample.y as f32);
+        self.drivers.push(driver);
+        Ok(())
+    }
+
+    pub fn extend(&mut self, samples: impl IntoIterator<Item = LocationData>) -> Result<(), Box<dyn StdError>> {
+        samples.into_iter().try_for_each(|sample| self.push(&sample))
+    }
+
+    pub fn len(&self) -> usize {
+        self.times_ms.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.times_ms.is_empty()
+    }
+
+    pub fn get(&self, index: usize) -> Option<LocationData> {
+        let origin = self.origin?;
+        Some(LocationData {
+            x: *self.x.get(index)? as f64,
+            y: self.y[index] as f64,
+            date: origin + Duration::milliseconds(self.times_ms[index] as i64),
+            driver_number: self.driver_numbers[self.drivers[index] as usize],
+        })
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = LocationData> + '_ {
+        (0..self.len()).filter_map(|index| self.get(index))
+    }
+
+    // Every driver with at least one sample, in order of appearance
+    pub fn driver_numbers(&self) -> &[u32] {
+        &self.driver_numbers
+    }
+
+    // Stable, so samples with the same time keep their order
+    pub fn sort_by_date(&mut self) {
+        let mut order: Vec<u32> = (0..self.len() as u32).collect();
+        order.sort_by_key(|&index| self.times_ms[index as usize]);
+        fn reorder<T: Copy>(column: &mut Vec<T>, order: &[u32]) {
+            *column = order.iter().map(|&index| column[index as usize]).collect();
+        }
+        reorder(&mut self.times_ms, &order);
+        reorder(&mut self.x, &order);
+        reorder(&mut self.y, &order);
+        reorder(&mut self.drivers, &order);
+    }
+
+    pub fn shrink_to_fit(&mut self) {
+        self.times_ms.shrink_to_fit();
+        self.x.shrink_to_fit();
+        self.y.shrink_to_fit();
+        self.drivers.shrink_to_fit();
+    }
+
+    // Heap memory held, for reporting
+    pub fn heap_bytes(&self) -> usize {
+        self.times_ms.capacity() * 4
+            + self.x.capacity() * 4
+            + self.y.capacity() * 4
+            + self.drivers.capacity()
+            + self.driver_numbers.capacity() * 4
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sample(driver_number: u32, seconds: i64) -> LocationData {
+        LocationData {
+            x: 100.0,
+            y: -50.0,
+            date: DateTime::from_timestamp(1_693_141_200 + seconds, 0).unwrap(),
+            driver_number,
+        }
+    }
+
+    #[test]
+    fn samples_round_trip() {
+        let mut samples = LocationSamples::new();
+        samples.extend([sample(44, 2), sample(1, 1)]).unwrap();
+        samples.sort_by_date();
+        assert_eq!(samples.iter().collect::<Vec<_>>(), [sample(1, 1), sample(44, 2)]);
+        assert_eq!(samples.driver_numbers(), [44, 1]);
+    }
+
+    #[test]
+    fn a_257th_driver_is_an_error() {
+        let mut samples = LocationSamples::new();
+        samples.extend((0..256).map(|driver| sample(driver, 0))).unwrap();
+        assert!(samples.push(&sample(256, 1)).is_err());
+        assert_eq!(samples.len(), 256);
+        // Drivers already seen can still be stored
+        samples.push(&sample(255, 1)).unwrap();
+        assert_eq!(samples.get(256), Some(sample(255, 1)));
+    }
+
+    #[test]
+    fn samples_too_far_from_the_first_are_an_error() {
+        let mut samples = LocationSamples::new();
+        samples.push(&sample(1, 0)).unwrap();
+        let days = |days: i64| days * 86_400;
+        samples.push(&sample(1, days(24))).unwrap();
+        samples.push(&sample(1, -days(24))).unwrap();
+        assert!(samples.push(&sample(1, days(25))).is_err());
+        assert!(samples.push(&sample(2, -days(25))).is_err());
+        // Nothing of the refused samples was stored
+        assert_eq!(samples.len(), 3);
+        assert_eq!(samples.driver_numbers(), [1]);
+        assert_eq!(samples.get(2), Some(sample(1, -days(24))));
+    }
+}
@@ -0,0 +1,45 @@
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+
+use crate::driver_info::{DriverInfo, Rgb};
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RosterEntry {
+    pub driver_number: u32,
+    pub name: String,
+    pub team: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub color: Option<Rgb>, // Colour under the scheme used for the export
+}
+
+// Header of exported visualization data describing where the frames come from
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct SessionMetadata {
+    pub session_key: String,
+    #[serde(default)]
+    pub circuit: String,
+    #[serde(default)]
+    pub meeting_name: String,
+    #[serde(default)]
+    pub session_start: Option<DateTime<Utc>>, // UTC time of session time zero
+    #[serde(default)]
+    pub layout_id: String,
+    #[serde(default)]
+    pub drivers: Vec<RosterEntry>,
+}
+
+// Roster entries for the given cars, named and coloured from the driver list
+pub fn roster(driver_numbers: &[u32], driver_info: &[DriverInfo]) -> Vec<RosterEntry> {
+    driver_numbers
+        .iter()
+        .map(|&driver_number| {
+            let info = driver_info.iter().find(|d| d.number == driver_number);
+            RosterEntry {
+                driver_number,
+                name: info.map_or(String::new(), |d| d.name.to_string()),
+                team: info.map_or(String::new(), |d| d.team.to_string()),
+                color: info.map(|d| d.color),
+            }
+        })
+        .collect()
+}
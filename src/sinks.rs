@@ -0,0 +1,313 @@
+use std::collections::VecDeque;
+use std::sync::{Arc, Condvar, Mutex};
+use std::thread::{self, JoinHandle};
+use std::time::Duration;
+
+use crate::driver_info::Rgb;
+use crate::hardware::LedOutput;
+
+// What a sink does with frames that pile up while its output is still busy
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DropPolicy {
+    // Only the newest frame waits, live boards skip ahead to the current one
+    Latest,
+    // Up to this many frames wait, the oldest are dropped beyond that
+    Queue(usize),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SinkOptions {
+    // Time from sending a frame to it being visible; the sink is fed that
+    // much earlier so it lights up together with the others
+    pub latency: Duration,
+    pub policy: DropPolicy,
+}
+
+impl Default for SinkOptions {
+    fn default() -> SinkOptions {
+        SinkOptions {
+            latency: Duration::ZERO,
+            policy: DropPolicy::Latest,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct SinkStats {
+    pub sent: usize,
+    pub dropped: usize,
+    pub errors: usize,
+}
+
+#[derive(Default)]
+struct Pending {
+    frames: VecDeque<Vec<Rgb>>,
+    stats: SinkStats,
+    closed: bool,
+}
+
+// One output with its own thread, so a slow serial link never stalls
+// playback or the other outputs
+pub struct OutputSink {
+    name: String,
+    options: SinkOptions,
+    pending: Arc<(Mutex<Pending>, Condvar)>,
+    worker: Option<JoinHandle<()>>,
+    last_index: Option<usize>, // Frame last queued
+    last_colors: Vec<Rgb>,
+}
+
+impl OutputSink {
+    pub fn new(name: &str, mut output: Box<dyn LedOutput>, options: SinkOptions) -> OutputSink {
+        let pending = Arc::new((Mutex::new(Pending::default()), Condvar::new()));
+        let shared = pending.clone();
+        let sink_name = name.to_string();
+        let worker = thread::spawn(move || {
+            let (lock, queued) = &*shared;
+            loop {
+                let colors = {
+                    let mut pending = queued
+                        .wait_while(lock.lock().unwrap(), |pending| pending.frames.is_empty() && !pending.closed)
+                        .unwrap();
+                    // Frames queued before closing still go out, e.g. the dark frame on reset
+                    match pending.frames.pop_front() {
+                        Some(colors) => colors,
+                        None => return,
+                    }
+                };
+                let result = output.send(&colors);
+                let mut pending = lock.lock().unwrap();
+                match result {
+                    Ok(()) => pending.stats.sent += 1,
+                    Err(e) => {
+                        pending.stats.errors += 1;
+                        eprintln!("Failed to send frame to LED output {}: {}", sink_name, e);
+                    }
+                }
+            }
+        });
+
+        OutputSink {
+            name: name.to_string(),
+            options,
+            pending,
+            worker: Some(worker),
+            last_index: None,
+            last_colors: Vec::new(),
+        }
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    pub fn options(&self) -> SinkOptions {
+        self.options
+    }
+
+    pub fn stats(&self) -> SinkStats {
+        self.pending.0.lock().unwrap().stats
+    }
+
+    fn queue(&mut self, index: Option<usize>, colors: Vec<Rgb>) {
+        let (lock, queued) = &*self.pending;
+        let mut pending = lock.lock().unwrap();
+        let keep = match self.options.policy {
+            DropPolicy::Latest => 0,
+            DropPolicy::Queue(max_frames) => max_frames.saturating_sub(1),
+        };
+        while pending.frames.len() > keep {
+            pending.frames.pop_front();
+            pending.stats.dropped += 1;
+        }
+        pending.frames.push_back(colors.clone());
+        queued.notify_one();
+
+        self.last_index = index;
+        self.last_colors = colors;
+    }
+}
+
+impl Drop for OutputSink {
+    fn drop(&mut self) {
+        let (lock, queued) = &*self.pending;
+        lock.lock().unwrap().closed = true;
+        queued.notify_one();
+        if let Some(worker) = self.worker.take() {
+            let _ = worker.join();
+        }
+    }
+}
+
+// Every output fed from one playback clock. Each sink is given the frame due
+// at `race_time + latency`, so boards with a slow link are sent frames early
+// and light up together with the screen.
+#[derive(Default)]
+pub struct OutputSinks {
+    sinks: Vec<OutputSink>,
+}
+
+impl OutputSinks {
+    pub fn new() -> OutputSinks {
+        OutputSinks::default()
+    }
+
+    pub fn add(&mut self, name: &str, output: Box<dyn LedOutput>, options: SinkOptions) {
+        self.sinks.push(OutputSink::new(name, output, options));
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.sinks.is_empty()
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &OutputSink> {
+        self.sinks.iter()
+    }
+
+    // `frame_at` gives the frame index and output colours at a race time, or
+    // `None` before the first frame. A sink is sent a frame when its index
+    // changes, or its colours do (e.g. a driver was hidden).
+    pub fn update<F>(&mut self, race_time: f64, speed: f64, mut frame_at: F)
+    where
+        F: FnMut(f64) -> Option<(usize, Vec<Rgb>)>,
+    {
+        for sink in &mut self.sinks {
+            // Latency is wall time, the race clock runs `speed` times faster
+            let due = race_time + sink.options.latency.as_secs_f64() * speed;
+            let Some((index, colors)) = frame_at(due) else {
+                continue;
+            };
+            if sink.last_index != Some(index) || sink.last_colors != colors {
+                sink.queue(Some(index), colors);
+            }
+        }
+    }
+
+    // Whether every sink has been sent frame `index` or a later one
+    pub fn reached(&self, index: usize) -> bool {
+        self.sinks.iter().all(|sink| sink.last_index.is_some_and(|last| last >= index))
+    }
+
+    // Sends the same colours to every sink straight away, e.g. all dark on reset
+    pub fn send_all(&mut self, colors: &[Rgb]) {
+        for sink in &mut self.sinks {
+            sink.queue(None, colors.to_vec());
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io;
+    use std::sync::mpsc::{self, Receiver, Sender};
+
+    // An output that is busy with every frame until the test releases it
+    struct SlowOutput {
+        started: Sender<u8>,
+        release: Receiver<()>,
+        sent: Arc<Mutex<Vec<u8>>>,
+    }
+
+    impl LedOutput for SlowOutput {
+        fn send(&mut self, colors: &[Rgb]) -> io::Result<()> {
+            let _ = self.started.send(colors[0].r);
+            let _ = self.release.recv();
+            self.sent.lock().unwrap().push(colors[0].r);
+            Ok(())
+        }
+    }
+
+    struct Slow {
+        sink: OutputSink,
+        started: Receiver<u8>,
+        release: Sender<()>,
+        sent: Arc<Mutex<Vec<u8>>>,
+    }
+
+    fn slow_sink(policy: DropPolicy) -> Slow {
+        let (started, started_rx) = mpsc::channel();
+        let (release_tx, release) = mpsc::channel();
+        let sent = Arc::new(Mutex::new(Vec::new()));
+        let output = SlowOutput { started, release, sent: sent.clone() };
+        let options = SinkOptions { policy, ..SinkOptions::default() };
+        Slow {
+            sink: OutputSink::new("slow", Box::new(output), options),
+            started: started_rx,
+            release: release_tx,
+            sent,
+        }
+    }
+
+    fn frame(n: u8) -> Vec<Rgb> {
+        vec![Rgb::from_rgb(n, 0, 0)]
+    }
+
+    // Sends frame 1, then queues `2..=last` while the output is still busy
+    // with it; returns the frames that went out
+    fn pile_up(slow: Slow, last: u8) -> Vec<u8> {
+        let Slow { mut sink, started, release, sent } = slow;
+        sink.queue(Some(1), frame(1));
+        assert_eq!(started.recv().unwrap(), 1);
+        for n in 2..=last {
+            sink.queue(Some(n as usize), frame(n));
+        }
+        let dropped = sink.stats().dropped;
+
+        // Let every frame still waiting through
+        for _ in 0..=last {
+            release.send(()).unwrap();
+        }
+        drop(sink);
+        let sent = sent.lock().unwrap().clone();
+        assert_eq!(dropped + sent.len(), last as usize);
+        sent
+    }
+
+    #[test]
+    fn latest_keeps_only_the_newest_frame() {
+        assert_eq!(pile_up(slow_sink(DropPolicy::Latest), 6), vec![1, 6]);
+    }
+
+    #[test]
+    fn a_queue_drops_the_oldest_frames_past_its_length() {
+        assert_eq!(pile_up(slow_sink(DropPolicy::Queue(3)), 6), vec![1, 4, 5, 6]);
+        assert_eq!(pile_up(slow_sink(DropPolicy::Queue(10)), 6), vec![1, 2, 3, 4, 5, 6]);
+    }
+
+    // Counts frames without ever being busy
+    struct Counter(Arc<Mutex<Vec<u8>>>);
+
+    impl LedOutput for Counter {
+        fn send(&mut self, colors: &[Rgb]) -> io::Result<()> {
+            self.0.lock().unwrap().push(colors[0].r);
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn latency_shifts_the_frame_a_sink_is_sent() {
+        let sent: Vec<_> = (0..3).map(|_| Arc::new(Mutex::new(Vec::new()))).collect();
+        let mut sinks = OutputSinks::new();
+        for (latency_ms, sent) in [0, 100, 250].into_iter().zip(&sent) {
+            let options = SinkOptions { latency: Duration::from_millis(latency_ms), policy: DropPolicy::Queue(100) };
+            sinks.add(&latency_ms.to_string(), Box::new(Counter(sent.clone())), options);
+        }
+
+        // Frames are 100 ms long; at twice real time 100 ms of latency is
+        // 0.2 s of race time, so two frames ahead
+        let frame_at = |time: f64| {
+            let index = (time * 10.0 + 1e-9).floor() as usize;
+            Some((index, frame(index as u8)))
+        };
+        sinks.update(1.0, 2.0, frame_at);
+        assert!(sinks.reached(10) && !sinks.reached(11));
+        // The same frames again are not sent twice
+        sinks.update(1.05, 2.0, frame_at);
+        sinks.update(1.1, 2.0, frame_at);
+        drop(sinks);
+
+        let sent: Vec<Vec<u8>> = sent.iter().map(|sent| sent.lock().unwrap().clone()).collect();
+        assert_eq!(sent, vec![vec![10, 11], vec![12, 13], vec![15, 16]]);
+    }
+}
@@ -0,0 +1,176 @@
+use chrono::{DateTime, ParseError, Utc};
+
+// Parses a timestamp in any of the forms OpenF1 writes them:
+// `2023-08-27T12:58:56.200000+00:00`, with fewer or no fraction digits, or
+// without an offset, which is taken as UTC. Read by hand, about a third faster
+// than chrono's RFC 3339 parser, which handles anything else.
+pub fn parse_utc(s: &str) -> Result<DateTime<Utc>, ParseError> {
+    match parse_openf1(s.as_bytes()) {
+        Some(date) => Ok(date),
+        None => DateTime::parse_from_rfc3339(s).map(|date| date.with_timezone(&Utc)),
+    }
+}
+
+// `YYYY-MM-DD`, `T` or a space, `HH:MM:SS`, an optional fraction of any
+// precision (cut off at nanoseconds) and an optional `Z`, `+HH:MM`, `+HHMM`
+// or `+HH` offset
+fn parse_openf1(s: &[u8]) -> Option<DateTime<Utc>> {
+    if s.len() < 19
+        || s[4] != b'-'
+        || s[7] != b'-'
+        || !matches!(s[10], b'T' | b't' | b' ')
+        || s[13] != b':'
+        || s[16] != b':'
+    {
+        return None;
+    }
+    let days = days_from_civil(digits(&s[0..4])? as i64, digits(&s[5..7])?, digits(&s[8..10])?)?;
+
+    let mut rest = &s[19..];
+    let mut nanos = 0;
+    if let Some((b'.', fraction)) = rest.split_first() {
+        let len = fraction.iter().take_while(|b| b.is_ascii_digit()).count();
+        if len == 0 {
+            return None;
+        }
+        let kept = len.min(9);
+        nanos = digits(&fraction[..kept])? * 10u32.pow(9 - kept as u32);
+        rest = &fraction[len..];
+    }
+    let offset_minutes = match rest {
+        b"" | b"Z" | b"z" => 0,
+        [sign @ (b'+' | b'-'), offset @ ..] => {
+            let (hours, minutes) = match offset {
+                [h1, h2] => (digits(&[*h1, *h2])?, 0),
+                [h1, h2, m1, m2] | [h1, h2, b':', m1, m2] => (digits(&[*h1, *h2])?, digits(&[*m1, *m2])?),
+                _ => return None,
+            };
+            if hours > 23 || minutes > 59 {
+                return None;
+            }
+            let minutes = (hours * 60 + minutes) as i64;
+            if *sign == b'-' {
+                -minutes
+            } else {
+                minutes
+            }
+        }
+        _ => return None,
+    };
+
+    let (hour, minute, mut second) = (digits(&s[11..13])?, digits(&s[14..16])?, digits(&s[17..19])?);
+    if hour > 23 || minute > 59 || second > 60 {
+        return None;
+    }
+    // chrono keeps a leap second as the 59th running past a billion nanoseconds
+    if second == 60 {
+        second = 59;
+        nanos += 1_000_000_000;
+    }
+    let seconds = days * 86_400 + (hour * 3600 + minute * 60 + second) as i64 - offset_minutes * 60;
+    DateTime::from_timestamp(seconds, nanos)
+}
+
+// Days since 1970-01-01 of a proleptic Gregorian date, `None` if it does not exist
+fn days_from_civil(year: i64, month: u32, day: u32) -> Option<i64> {
+    let leap = year % 4 == 0 && (year % 100 != 0 || year % 400 == 0);
+    let month_days = [31, if leap { 29 } else { 28 }, 31, 30, 31, 30, 31, 31, 30, 31, 30, 31];
+    if day == 0 || day > *month_days.get(month.checked_sub(1)? as usize)? {
+        return None;
+    }
+    // Years starting in March put the leap day last
+    let year = if month <= 2 { year - 1 } else { year };
+    let era = year.div_euclid(400);
+    let year_of_era = year - era * 400;
+    let day_of_year = (153 * ((month as i64 + 9) % 12) + 2) / 5 + day as i64 - 1;
+    let day_of_era = year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+    Some(era * 146_097 + day_of_era - 719_468)
+}
+
+fn digits(bytes: &[u8]) -> Option<u32> {
+    bytes
+        .iter()
+        .try_fold(0u32, |n, &b| b.is_ascii_digit().then(|| n * 10 + (b - b'0') as u32))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::{NaiveDate, Timelike};
+
+    fn utc(hour: u32, minute: u32, second: u32, nanos: u32) -> DateTime<Utc> {
+        NaiveDate::from_ymd_opt(2023, 8, 27)
+            .unwrap()
+            .and_hms_nano_opt(hour, minute, second, nanos)
+            .unwrap()
+            .and_utc()
+    }
+
+    #[test]
+    fn offsets_and_separators() {
+        let expected = utc(12, 58, 56, 200_000_000);
+        for s in [
+            "2023-08-27T12:58:56.200000+00:00",
+            "2023-08-27T12:58:56.2Z",
+            "2023-08-27t12:58:56.2z",
+            "2023-08-27T12:58:56.2+0000",
+            "2023-08-27T13:58:56.2+01",
+            "2023-08-27T18:28:56.2+05:30",
+            "2023-08-27T07:58:56.2-0500",
+            "2023-08-27T12:58:56.2", // No offset is UTC
+            "2023-08-27 12:58:56.2",
+        ] {
+            // Read by hand, not by the fallback
+            assert_eq!(parse_openf1(s.as_bytes()), Some(expected), "{}", s);
+            assert_eq!(parse_utc(s).unwrap(), expected, "{}", s);
+        }
+    }
+
+    #[test]
+    fn fractions_of_any_length() {
+        for (s, nanos) in [
+            ("2023-08-27T12:58:56Z", 0),
+            ("2023-08-27T12:58:56.250Z", 250_000_000),
+            ("2023-08-27T12:58:56.250123Z", 250_123_000),
+            ("2023-08-27T12:58:56.123456789Z", 123_456_789),
+            ("2023-08-27T12:58:56.1234567891234Z", 123_456_789), // Cut off at nanoseconds
+        ] {
+            assert_eq!(parse_utc(s).unwrap(), utc(12, 58, 56, nanos), "{}", s);
+            // The same as chrono's parser
+            assert_eq!(parse_utc(s).unwrap(), DateTime::parse_from_rfc3339(s).unwrap(), "{}", s);
+        }
+    }
+
+    #[test]
+    fn leap_seconds_match_chrono() {
+        let s = "2016-12-31T23:59:60.5Z";
+        let date = parse_openf1(s.as_bytes()).unwrap();
+        assert_eq!(date, DateTime::parse_from_rfc3339(s).unwrap());
+        assert_eq!((date.second(), date.nanosecond()), (59, 1_500_000_000));
+    }
+
+    #[test]
+    fn invalid_dates_and_offsets_are_errors() {
+        for s in [
+            "",
+            "2023-08-27",
+            "2023-02-29T12:00:00Z", // Not a leap year
+            "2023-13-01T12:00:00Z",
+            "2023-08-00T12:00:00Z",
+            "2023-08-27T24:00:00Z",
+            "2023-08-27T12:60:00Z",
+            "2023-08-27T12:00:61Z",
+            "2023-08-27T12:00:00.Z",
+            "2023-08-27T12:00:00+24:00",
+            "2023-08-27T12:00:00+01:60",
+            "2023-08-27T12:00:00+1",
+            "2023-08-27T12:00:00+01:0",
+            "2023-08-27T12:00:00 UTC",
+            "2023-08-27X12:00:00Z",
+            "2023-8-27T12:00:00Z",
+        ] {
+            assert!(parse_utc(s).is_err(), "{}", s);
+        }
+        assert_eq!(parse_utc("2024-02-29T12:00:00Z").unwrap().timestamp(), 1_709_208_000);
+    }
+}
@@ -0,0 +1,136 @@
+use eframe::{egui, App, Frame};
+use std::sync::{Arc, Mutex};
+
+use crate::driver_info::get_driver_info;
+use crate::gui::{PlotApp, UiState};
+use crate::layout::{read_layout, TrackLayout};
+use crate::VisualizationData;
+
+// Exported session served next to index.html, overridden with `?data=<url>`
+// or the session of the previous visit
+pub const DEFAULT_DATA_URL: &str = "race_data.json";
+
+type Loaded = Result<(VisualizationData, TrackLayout), String>;
+
+// Browser entry point: shows a loading screen until the session (and an
+// optional `?layout=<url>`) has been fetched, then runs the simulator
+pub struct WebApp {
+    pending: Arc<Mutex<Option<Loaded>>>,
+    data_url: String,
+    layout_url: Option<String>,
+    saved: UiState,
+    state: WebState,
+}
+
+enum WebState {
+    Loading,
+    Failed(String),
+    Running(Box<PlotApp>),
+}
+
+impl WebApp {
+    pub fn new(cc: &eframe::CreationContext<'_>) -> WebApp {
+        let saved = UiState::restore(cc.storage);
+        let query = &cc.integration_info.web_info.location.query_map;
+        let data_url = query
+            .get("data")
+            .cloned()
+            .or_else(|| saved.data_source.clone())
+            .unwrap_or_else(|| DEFAULT_DATA_URL.to_string());
+        let layout_url = query.get("layout").cloned().or_else(|| saved.layout_source.clone());
+
+        let pending = Arc::new(Mutex::new(None));
+        let result = pending.clone();
+        let ctx = cc.egui_ctx.clone();
+        let (url, layout) = (data_url.clone(), layout_url.clone());
+        wasm_bindgen_futures::spawn_local(async move {
+            let loaded = load_session(&url, layout.as_deref()).await;
+            *result.lock().unwrap() = Some(loaded);
+            ctx.request_repaint();
+        });
+
+        WebApp {
+            pending,
+            data_url,
+            layout_url,
+            saved,
+            state: WebState::Loading,
+        }
+    }
+}
+
+impl App for WebApp {
+    fn update(&mut self, ctx: &egui::Context, frame: &mut Frame) {
+        if let Some(loaded) = self.pending.lock().unwrap().take() {
+            self.state = match loaded {
+                Ok((data, layout)) => {
+                    let mut app = PlotApp::new(data.update_rate_ms, data.frames, layout, get_driver_info());
+                    app.set_sources(Some(self.data_url.clone()), self.layout_url.clone());
+                    app.restore_state(self.saved.clone());
+                    WebState::Running(Box::new(app))
+                }
+                Err(e) => WebState::Failed(e),
+            };
+        }
+
+        match &mut self.state {
+            WebState::Running(app) => app.update(ctx, frame),
+            WebState::Loading => {
+                egui::CentralPanel::default().show(ctx, |ui| {
+                    ui.centered_and_justified(|ui| {
+                        ui.label(format!("Loading {} ...", self.data_url));
+                    });
+                });
+            }
+            WebState::Failed(e) => {
+                egui::CentralPanel::default().show(ctx, |ui| {
+                    ui.centered_and_justified(|ui| {
+                        ui.label(format!("Failed to load {}: {}", self.data_url, e));
+                    });
+                });
+            }
+        }
+    }
+
+    // Nothing is saved until a session loads, so a bad URL doesn't replace the last good one
+    fn save(&mut self, storage: &mut dyn eframe::Storage) {
+        if let WebState::Running(app) = &mut self.state {
+            app.save(storage);
+        }
+    }
+}
+
+// Fetches through the browser; relative URLs resolve against the page
+async fn load_session(data_url: &str, layout_url: Option<&str>) -> Loaded {
+    let data: VisualizationData = fetch_json(data_url).await?;
+    let layout = match layout_url {
+        Some(url) => fetch_json(url).await?,
+        None => read_layout().map_err(|e| e.to_string())?,
+    };
+    Ok((data, layout))
+}
+
+async fn fetch_json<T: serde::de::DeserializeOwned>(url: &str) -> Result<T, String> {
+    let url = absolute_url(url)?;
+    let bytes = reqwest::get(&url)
+        .await
+        .and_then(|resp| resp.error_for_status())
+        .map_err(|e| e.to_string())?
+        .bytes()
+        .await
+        .map_err(|e| e.to_string())?;
+    serde_json::from_slice(&bytes).map_err(|e| e.to_string())
+}
+
+// reqwest needs absolute URLs, so resolve against the page location
+fn absolute_url(url: &str) -> Result<String, String> {
+    if url.contains("://") {
+        return Ok(url.to_string());
+    }
+    let base = web_sys::window()
+        .and_then(|window| window.location().href().ok())
+        .ok_or("no page location")?;
+    web_sys::Url::new_with_base(url, &base)
+        .map(|resolved| resolved.href())
+        .map_err(|_| format!("invalid URL {}", url))
+}
@@ -0,0 +1,45 @@
+// The generated firmware module compiles with `include!` and plays back as
+// its comments describe. `tests/firmware/race_data.rs` is generated by
+// `FirmwareExport`, a unit test there keeps it up to date.
+use std::collections::BTreeMap;
+
+mod race_data {
+    include!("firmware/race_data.rs");
+}
+
+use race_data::*;
+
+// LED of every car on the board after frame `index`, played from its keyframe
+fn play(index: usize) -> BTreeMap<u8, u8> {
+    let keyframe = match KEYFRAME_INTERVAL {
+        0 => index,
+        interval => index - index % interval,
+    };
+    let mut cars = BTreeMap::new();
+    for frame in keyframe..=index {
+        let (start, end) = (FRAME_OFFSETS[frame] as usize, FRAME_OFFSETS[frame + 1] as usize);
+        for entry in &DRIVER_LEDS[start..end] {
+            match entry.led {
+                0 => cars.remove(&entry.driver_number),
+                led => cars.insert(entry.driver_number, led),
+            };
+        }
+    }
+    cars
+}
+
+#[test]
+fn generated_module_plays_back() {
+    assert_eq!(FRAME_COUNT, 4);
+    assert_eq!(FRAME_OFFSETS.len(), FRAME_COUNT + 1);
+    assert_eq!(KEYFRAME_INTERVAL, 2);
+
+    let frames: Vec<Vec<(u8, u8)>> = (0..FRAME_COUNT).map(|index| play(index).into_iter().collect()).collect();
+    // Car 44 runs off the 96 LEDs in frame 1, comes back and retires in frame 3
+    assert_eq!(frames, [vec![(1, 10), (44, 20)], vec![(1, 11)], vec![(1, 12), (44, 21)], vec![(1, 13)]]);
+    assert!(DRIVER_LEDS.iter().all(|entry| usize::from(entry.led) <= LED_COUNT));
+
+    assert_eq!(DRIVER_COLORS[0].driver_number, 44);
+    assert_eq!(DRIVER_COLORS[0].rgb, [0, 210, 190]);
+    assert_eq!(UPDATE_RATE_MS, 250);
+}
@@ -0,0 +1,56 @@
+// Generated by f1-led-circuit-master-simulation. Do not edit.
+// Only uses `core`, so it can be included in `no_std` firmware.
+
+#[allow(dead_code)]
+pub const LED_COUNT: usize = 96;
+#[allow(dead_code)]
+pub const UPDATE_RATE_MS: u32 = 250;
+#[allow(dead_code)]
+pub const FRAME_COUNT: usize = 4;
+#[allow(dead_code)]
+pub const KEYFRAME_INTERVAL: usize = 2;
+
+#[allow(dead_code)]
+#[derive(Clone, Copy)]
+pub struct DriverLed {
+    pub driver_number: u8,
+    pub led: u8, // 1-based, 0 takes the car off
+}
+
+// Entries of frame `i` are DRIVER_LEDS[FRAME_OFFSETS[i]..FRAME_OFFSETS[i + 1]]
+// Playing frame i: with a keyframe interval of 0 every frame is stored in
+// full, so start from no cars. Otherwise frame i is a keyframe when
+// i % interval == 0 and holds every car, so start from no cars; any other
+// frame only holds the cars that changed since frame i - 1, so start from
+// the cars of frame i - 1. Then move each entry's car to its LED; LED 0
+// takes the car off the board (it retired or left the LEDs). Cars not in
+// the frame stay where they are. To seek, play from the last keyframe at
+// or before the frame.
+#[allow(dead_code)]
+pub static FRAME_OFFSETS: [u32; 5] = [
+    0, 2, 4, 6, 8,
+];
+
+#[allow(dead_code)]
+pub static DRIVER_LEDS: [DriverLed; 8] = [
+    DriverLed { driver_number: 1, led: 10 },
+    DriverLed { driver_number: 44, led: 20 },
+    DriverLed { driver_number: 1, led: 11 },
+    DriverLed { driver_number: 44, led: 0 },
+    DriverLed { driver_number: 1, led: 12 },
+    DriverLed { driver_number: 44, led: 21 },
+    DriverLed { driver_number: 1, led: 13 },
+    DriverLed { driver_number: 44, led: 0 },
+];
+
+#[allow(dead_code)]
+#[derive(Clone, Copy)]
+pub struct DriverColor {
+    pub driver_number: u8,
+    pub rgb: [u8; 3],
+}
+
+#[allow(dead_code)]
+pub static DRIVER_COLORS: [DriverColor; 1] = [
+    DriverColor { driver_number: 44, rgb: [0, 210, 190] }, // Lewis Hamilton
+];
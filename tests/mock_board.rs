@@ -0,0 +1,83 @@
+// Frames sent through every hardware output arrive at the mock board intact
+#![cfg(all(feature = "hardware-serial", not(target_arch = "wasm32")))]
+
+use std::time::Duration;
+
+use f1_led_circuit_master_simulation::driver_info::Rgb;
+use f1_led_circuit_master_simulation::hardware::{LedOutput, RecorderOutput, UdpOutput};
+use f1_led_circuit_master_simulation::mock_board::{FrameSource, MockBoard};
+
+const TIMEOUT: Duration = Duration::from_secs(5);
+
+fn frames() -> Vec<Vec<Rgb>> {
+    (0..5u8)
+        .map(|i| (0..96u8).map(|led| Rgb::from_rgb(i, led, 255 - led)).collect())
+        .collect()
+}
+
+fn assert_received(board: &MockBoard, source: FrameSource) {
+    let received = board.wait_for_frames(frames().len(), TIMEOUT);
+    assert!(received.iter().all(|frame| frame.source == source));
+    let colors: Vec<Vec<Rgb>> = received.into_iter().map(|frame| frame.colors).collect();
+    assert_eq!(colors, frames());
+    assert_eq!(board.frame_count(), frames().len());
+    assert_eq!(board.decode_errors(), 0);
+}
+
+#[test]
+fn udp_frames_arrive() {
+    let mut board = MockBoard::new();
+    let addr = board.listen_udp("127.0.0.1:0").unwrap();
+    let mut output = UdpOutput::connect(&addr.to_string()).unwrap();
+    for colors in frames() {
+        output.send(&colors).unwrap();
+    }
+    assert_received(&board, FrameSource::Udp);
+}
+
+#[cfg(unix)]
+#[test]
+fn serial_frames_arrive() {
+    use f1_led_circuit_master_simulation::hardware::SerialOutput;
+
+    let mut board = MockBoard::new();
+    let path = board.open_serial().unwrap();
+    let mut output = SerialOutput::open(&path, 115_200).unwrap();
+    for colors in frames() {
+        output.send(&colors).unwrap();
+    }
+    assert_received(&board, FrameSource::Serial);
+}
+
+#[test]
+fn recorded_frames_read_back() {
+    let path = std::env::temp_dir().join(format!("mock_board_recording_{}.bin", std::process::id()));
+    let mut output = RecorderOutput::create(&path).unwrap();
+    for colors in frames() {
+        output.send(&colors).unwrap();
+    }
+    drop(output); // Flushes the file
+
+    let mut board = MockBoard::new();
+    board.read_recording(&path).unwrap();
+    std::fs::remove_file(&path).unwrap();
+    assert_received(&board, FrameSource::Recording);
+}
+
+#[test]
+fn a_truncated_recording_is_an_error() {
+    let path = std::env::temp_dir().join(format!("mock_board_truncated_{}.bin", std::process::id()));
+    let mut output = RecorderOutput::create(&path).unwrap();
+    for colors in frames() {
+        output.send(&colors).unwrap();
+    }
+    drop(output);
+    let bytes = std::fs::read(&path).unwrap();
+    std::fs::write(&path, &bytes[..bytes.len() - 10]).unwrap();
+
+    let mut board = MockBoard::new();
+    board.read_recording(&path).unwrap();
+    std::fs::remove_file(&path).unwrap();
+    assert_eq!(board.frame_count(), frames().len() - 1);
+    assert_eq!(board.decode_errors(), 1);
+}